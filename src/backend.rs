@@ -0,0 +1,220 @@
+use std::cell::{Cell, RefCell};
+use std::io::stdout;
+use std::time::Duration;
+
+use anathema::backend::tui::TuiBackend;
+use anathema::backend::Backend;
+use anathema::component::{KeyCode, KeyEvent};
+use anathema::geometry::Size;
+use anathema::widgets::components::events::{Event, KeyState, MouseButton, MouseEvent, MouseState};
+use anathema::widgets::GlyphMap;
+use crossterm::event::{
+    DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+    Event as CTEvent, KeyCode as CTKeyCode, KeyEventKind, KeyModifiers as CTKeyModifiers,
+    MouseButton as CTMouseButton, MouseEventKind,
+};
+
+thread_local!(static MODIFIERS: Cell<CTKeyModifiers> = Cell::new(CTKeyModifiers::NONE));
+thread_local!(static PASTE: RefCell<Option<String>> = Default::default());
+thread_local!(static MOUSE_CAPTURE: Cell<bool> = const { Cell::new(false) });
+thread_local!(static QUIT: Cell<bool> = const { Cell::new(false) });
+
+/// Returns true if shift was held during the last key event.
+///
+/// anathema's `KeyEvent` only reports ctrl, so the modifiers are tracked here instead.
+pub fn shift_held() -> bool {
+    MODIFIERS.get().contains(CTKeyModifiers::SHIFT)
+}
+
+/// Returns true if alt was held during the last key event.
+pub fn alt_held() -> bool {
+    MODIFIERS.get().contains(CTKeyModifiers::ALT)
+}
+
+/// Stops the runtime the next time the backend polls for events.
+///
+/// Components have no way of stopping the runtime themselves, so the backend reports an
+/// `Event::Stop` instead.
+pub fn quit() {
+    QUIT.set(true);
+}
+
+/// Takes the text of the last bracketed paste.
+///
+/// A paste is delivered to the focused component as a Ctrl-V key event, handlers for Ctrl-V
+/// should check this first and only fall back to their own clipboard if it is empty. The text is
+/// only available while that event is dispatched.
+pub fn take_paste() -> Option<String> {
+    PASTE.take()
+}
+
+/// Enables or disables mouse capture, applied the next time the backend polls for events.
+///
+/// Capturing the mouse stops the terminal from selecting text, so it is only enabled while the
+/// preview is showing.
+pub fn set_mouse_capture(enabled: bool) {
+    MOUSE_CAPTURE.set(enabled);
+}
+
+fn convert_mouse_button(button: CTMouseButton) -> MouseButton {
+    match button {
+        CTMouseButton::Left => MouseButton::Left,
+        CTMouseButton::Right => MouseButton::Right,
+        CTMouseButton::Middle => MouseButton::Middle,
+    }
+}
+
+/// Wraps the `TuiBackend` and reads the terminal events itself, so information that anathema
+/// drops during the conversion (like modifiers) is still available to the components.
+pub struct PlaygroundBackend {
+    inner: TuiBackend,
+    mouse_captured: bool,
+}
+
+impl PlaygroundBackend {
+    pub fn new(inner: TuiBackend) -> Self {
+        _ = crossterm::execute!(stdout(), EnableBracketedPaste);
+        Self {
+            inner,
+            mouse_captured: false,
+        }
+    }
+}
+
+impl Drop for PlaygroundBackend {
+    fn drop(&mut self) {
+        if self.mouse_captured {
+            _ = crossterm::execute!(stdout(), DisableMouseCapture);
+        }
+        _ = crossterm::execute!(stdout(), DisableBracketedPaste);
+    }
+}
+
+impl Backend for PlaygroundBackend {
+    fn size(&self) -> Size {
+        self.inner.size()
+    }
+
+    fn next_event(&mut self, timeout: Duration) -> Option<Event> {
+        if QUIT.get() {
+            return Some(Event::Stop);
+        }
+
+        // the previous event has been dispatched by now, a paste nobody took must not be
+        // inserted by a later Ctrl-V
+        PASTE.take();
+
+        let capture = MOUSE_CAPTURE.get();
+        if capture != self.mouse_captured {
+            self.mouse_captured = capture;
+            if capture {
+                _ = crossterm::execute!(stdout(), EnableMouseCapture);
+            } else {
+                _ = crossterm::execute!(stdout(), DisableMouseCapture);
+            }
+        }
+
+        if !crossterm::event::poll(timeout).ok()? {
+            return None;
+        }
+
+        match crossterm::event::read().ok()? {
+            CTEvent::Key(key) => {
+                MODIFIERS.set(key.modifiers);
+                let code = match key.code {
+                    CTKeyCode::Backspace => KeyCode::Backspace,
+                    CTKeyCode::Enter => KeyCode::Enter,
+                    CTKeyCode::Left => KeyCode::Left,
+                    CTKeyCode::Right => KeyCode::Right,
+                    CTKeyCode::Up => KeyCode::Up,
+                    CTKeyCode::Down => KeyCode::Down,
+                    CTKeyCode::Home => KeyCode::Home,
+                    CTKeyCode::End => KeyCode::End,
+                    CTKeyCode::PageUp => KeyCode::PageUp,
+                    CTKeyCode::PageDown => KeyCode::PageDown,
+                    CTKeyCode::Tab => KeyCode::Tab,
+                    CTKeyCode::BackTab => KeyCode::BackTab,
+                    CTKeyCode::Delete => KeyCode::Delete,
+                    CTKeyCode::Insert => KeyCode::Insert,
+                    CTKeyCode::F(n) => KeyCode::F(n),
+                    CTKeyCode::Char(c) => KeyCode::Char(c),
+                    CTKeyCode::Null => KeyCode::Null,
+                    CTKeyCode::Esc => KeyCode::Esc,
+                    _ => return None,
+                };
+                let state = match key.kind {
+                    KeyEventKind::Press => KeyState::Press,
+                    KeyEventKind::Repeat => KeyState::Repeat,
+                    KeyEventKind::Release => KeyState::Release,
+                };
+
+                Some(Event::Key(KeyEvent {
+                    code,
+                    ctrl: key.modifiers.contains(CTKeyModifiers::CONTROL),
+                    state,
+                }))
+            }
+            CTEvent::Resize(width, height) => Some(Event::Resize(width, height)),
+            CTEvent::FocusGained => Some(Event::Focus),
+            CTEvent::FocusLost => Some(Event::Blur),
+            CTEvent::Paste(text) => {
+                PASTE.set(Some(text.replace('\r', "")));
+                MODIFIERS.set(CTKeyModifiers::CONTROL);
+                Some(Event::Key(KeyEvent {
+                    code: KeyCode::Char('v'),
+                    ctrl: true,
+                    state: KeyState::Press,
+                }))
+            }
+            CTEvent::Mouse(mouse) => {
+                let state = match mouse.kind {
+                    MouseEventKind::Down(button) => MouseState::Down(convert_mouse_button(button)),
+                    MouseEventKind::Up(button) => MouseState::Up(convert_mouse_button(button)),
+                    MouseEventKind::Drag(button) => MouseState::Drag(convert_mouse_button(button)),
+                    MouseEventKind::Moved => MouseState::Move,
+                    MouseEventKind::ScrollDown => MouseState::ScrollDown,
+                    MouseEventKind::ScrollUp => MouseState::ScrollUp,
+                    _ => return None,
+                };
+
+                Some(Event::Mouse(MouseEvent {
+                    x: mouse.column,
+                    y: mouse.row,
+                    state,
+                    ctrl: mouse.modifiers.contains(CTKeyModifiers::CONTROL),
+                }))
+            }
+        }
+    }
+
+    fn resize(&mut self, new_size: Size) {
+        self.inner.resize(new_size);
+    }
+
+    fn paint<'bp>(
+        &mut self,
+        glyph_map: &mut GlyphMap,
+        element: &mut anathema::widgets::Element<'bp>,
+        children: &[anathema::store::tree::Node],
+        values: &mut anathema::store::tree::TreeValues<anathema::widgets::WidgetKind<'bp>>,
+        attribute_storage: &anathema::widgets::AttributeStorage<'bp>,
+        ignore_floats: bool,
+    ) {
+        self.inner.paint(
+            glyph_map,
+            element,
+            children,
+            values,
+            attribute_storage,
+            ignore_floats,
+        )
+    }
+
+    fn render(&mut self, glyph_map: &mut GlyphMap) {
+        self.inner.render(glyph_map);
+    }
+
+    fn clear(&mut self) {
+        self.inner.clear();
+    }
+}
@@ -0,0 +1,90 @@
+use std::io::Write;
+
+// maximum amount of entries kept in the kill ring
+const KILL_RING_SIZE: usize = 16;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub struct Clipboard {
+    // most recent entry first
+    kill_ring: Vec<String>,
+    osc52: bool,
+}
+
+impl Clipboard {
+    /// If `osc52` is true, copied text is also sent to the host terminal's clipboard
+    pub fn new(osc52: bool) -> Self {
+        Self {
+            kill_ring: Vec::new(),
+            osc52,
+        }
+    }
+
+    pub fn copy(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        if self.osc52 {
+            write_osc52(&text);
+        }
+        self.kill_ring.insert(0, text);
+        self.kill_ring.truncate(KILL_RING_SIZE);
+    }
+
+    /// Returns the most recently copied text
+    pub fn paste(&self) -> Option<&str> {
+        self.kill_ring.first().map(String::as_str)
+    }
+}
+
+// OSC 52 lets the terminal set its clipboard, which also works over ssh
+fn write_osc52(text: &str) {
+    let mut stdout = std::io::stdout();
+    _ = write!(stdout, "\x1b]52;c;{}\x07", base64_encode(text.as_bytes()));
+    _ = stdout.flush();
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(n >> (18 - i * 6)) as usize & 0x3f] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn base64_rfc_4648_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (input, expected) in vectors {
+            assert_eq!(base64_encode(input.as_bytes()), expected, "{input:?}");
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use anathema::backend::tui::Style;
+use anathema::default_widgets::Canvas;
+use anathema::geometry::Size;
+use anathema::state::Color;
+
+use crate::thread_backend::RenderedBuffer;
+
+/// Terminal sizes the preview can be rendered at instead of the size of its pane.
+pub const PRESETS: [(usize, usize); 2] = [(80, 24), (120, 40)];
+
+// a tick is drawn on the ruler every this many columns / rows
+const RULER_COLUMNS: usize = 10;
+const RULER_ROWS: usize = 5;
+
+// the largest simulated size, the preview allocates a buffer of this many cells
+const MAX_SIZE: usize = 1000;
+
+/// Parses a size written as `WxH`, e.g. `100x30`.
+pub fn parse(text: &str) -> Result<Size, String> {
+    let invalid = || format!("{:?} is not a size like 80x24", text.trim());
+    let (width, height) = text.trim().split_once(['x', 'X']).ok_or_else(invalid)?;
+    let width: usize = width.trim().parse().map_err(|_| invalid())?;
+    let height: usize = height.trim().parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    if width > MAX_SIZE || height > MAX_SIZE {
+        return Err(format!(
+            "{width}x{height} is too large, the preview can be at most {MAX_SIZE}x{MAX_SIZE}"
+        ));
+    }
+    Ok(Size::new(width, height))
+}
+
+pub fn label(size: Size) -> String {
+    format!("{}x{}", size.width, size.height)
+}
+
+/// Where the simulated terminal ends up inside the preview pane: centred, inside a one cell frame,
+/// and clipped if the pane is too small to show all of it.
+#[derive(Clone, Copy)]
+pub struct Placement {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Placement {
+    pub fn new(pane: Size, device: Size) -> Self {
+        let width = device.width.min(pane.width.saturating_sub(2));
+        let height = device.height.min(pane.height.saturating_sub(2));
+        Self {
+            x: (pane.width.saturating_sub(width + 2)) / 2 + 1,
+            y: (pane.height.saturating_sub(height + 2)) / 2 + 1,
+            width,
+            height,
+        }
+    }
+
+    /// Translates a position in the pane into the simulated terminal.
+    pub fn to_device(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let x = x.checked_sub(self.x).filter(|x| *x < self.width)?;
+        let y = y.checked_sub(self.y).filter(|y| *y < self.height)?;
+        Some((x, y))
+    }
+}
+
+/// Draws `buffer` into a frame in the middle of the canvas. The top and left edges of the frame
+/// are rulers, and the top edge is labelled with the simulated size.
+pub fn draw_framed(canvas: &mut Canvas, canvas_size: Size, device: Size, buffer: &RenderedBuffer) {
+    for y in 0..canvas_size.height {
+        for x in 0..canvas_size.width {
+            canvas.erase((x as u16, y as u16));
+        }
+    }
+    if canvas_size.width < 3 || canvas_size.height < 3 {
+        return;
+    }
+
+    let placement = Placement::new(canvas_size, device);
+    let mut frame_style = Style::new();
+    frame_style.set_fg(Color::DarkGrey);
+    let left = placement.x - 1;
+    let top = placement.y - 1;
+    let right = placement.x + placement.width;
+    let bottom = placement.y + placement.height;
+
+    for x in placement.x..right {
+        let column = x - placement.x;
+        let ruler = if column > 0 && column % RULER_COLUMNS == 0 {
+            '┬'
+        } else {
+            '─'
+        };
+        canvas.put(ruler, frame_style, (x as u16, top as u16));
+        canvas.put('─', frame_style, (x as u16, bottom as u16));
+    }
+    for y in placement.y..bottom {
+        let row = y - placement.y;
+        let ruler = if row > 0 && row % RULER_ROWS == 0 {
+            '├'
+        } else {
+            '│'
+        };
+        canvas.put(ruler, frame_style, (left as u16, y as u16));
+        canvas.put('│', frame_style, (right as u16, y as u16));
+    }
+    canvas.put('┌', frame_style, (left as u16, top as u16));
+    canvas.put('┐', frame_style, (right as u16, top as u16));
+    canvas.put('└', frame_style, (left as u16, bottom as u16));
+    canvas.put('┘', frame_style, (right as u16, bottom as u16));
+
+    let title = if placement.width < device.width || placement.height < device.height {
+        format!(" {} (clipped) ", label(device))
+    } else {
+        format!(" {} ", label(device))
+    };
+    let mut title_style = Style::new();
+    title_style.set_fg(Color::Yellow);
+    for (offset, c) in title
+        .chars()
+        .take(placement.width.saturating_sub(1))
+        .enumerate()
+    {
+        canvas.put(
+            c,
+            title_style,
+            ((placement.x + 1 + offset) as u16, top as u16),
+        );
+    }
+
+    let buffer_size = buffer.size();
+    for y in 0..placement.height.min(buffer_size.height) {
+        for x in 0..placement.width.min(buffer_size.width) {
+            let pos = ((placement.x + x) as u16, (placement.y + y) as u16);
+            let cell = buffer.get(x, y);
+            // a wide grapheme cut off by the frame would be drawn over it
+            if x + usize::from(cell.width) > placement.width {
+                canvas.erase(pos);
+            } else {
+                cell.paint(canvas, pos);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse("80x24"), Ok(Size::new(80, 24)));
+        assert_eq!(parse(" 120 X 40 "), Ok(Size::new(120, 40)));
+        assert_eq!(parse("1000x1000"), Ok(Size::new(1000, 1000)));
+    }
+
+    #[test]
+    fn parse_invalid_sizes() {
+        for text in ["0x5", "5x0", "abc", "80", "80x", "-1x5", ""] {
+            assert!(parse(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn parse_rejects_huge_sizes() {
+        let error = parse("100000x100000").unwrap_err();
+        assert!(error.contains("at most 1000x1000"), "{error}");
+        assert!(parse("1001x10").is_err());
+    }
+
+    #[test]
+    fn placement_centres_the_device() {
+        let placement = Placement::new(Size::new(100, 30), Size::new(80, 24));
+        assert_eq!(
+            (placement.x, placement.y, placement.width, placement.height),
+            (10, 3, 80, 24)
+        );
+        assert_eq!(placement.to_device(10, 3), Some((0, 0)));
+        assert_eq!(placement.to_device(89, 26), Some((79, 23)));
+        assert_eq!(placement.to_device(9, 3), None);
+        assert_eq!(placement.to_device(90, 3), None);
+    }
+
+    #[test]
+    fn placement_clips_to_the_pane() {
+        // one cell of the pane is left for the frame on every side
+        let placement = Placement::new(Size::new(50, 20), Size::new(80, 24));
+        assert_eq!(
+            (placement.x, placement.y, placement.width, placement.height),
+            (1, 1, 48, 18)
+        );
+        assert_eq!(placement.to_device(48, 18), Some((47, 17)));
+        assert_eq!(placement.to_device(49, 19), None);
+    }
+}
@@ -1,103 +1,692 @@
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
     path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
 use anathema::{
     component::{Component, KeyCode, KeyEvent},
     geometry::Size,
     prelude::Context,
-    state::{State, Value},
+    state::{List, State, Value},
     widgets::{components::events::KeyState, Elements},
 };
 
 use crate::{
+    backend,
+    clipboard::Clipboard,
+    preview_state::PreviewState,
+    project::Project,
+    swap,
     text_buffer::TextBuffer,
-    thread_backend::{launch_threaded_anathema, AnathemaThreadHandle},
+    thread_backend::{launch_anathema, AnathemaThreadHandle, CompileError, PreviewMode},
 };
 
+#[derive(State)]
+pub struct Tab {
+    name: Value<String>,
+    dirty: Value<bool>,
+    active: Value<bool>,
+}
+
 #[derive(State)]
 pub struct EditorState {
     width: Value<usize>,
     height: Value<usize>,
     focused: Value<bool>,
-    dirty: Value<bool>,
-    file: Value<String>,
+    tabs: Value<List<Tab>>,
+    search_status: Value<String>,
+    regex: Value<bool>,
+    replace_mode: Value<bool>,
+    live: Value<bool>,
+    save_as: Value<bool>,
+    conflict: Value<String>,
+    diagnostics: Value<String>,
+    has_diagnostics: Value<bool>,
+    error: Value<String>,
+    project: Value<bool>,
 }
 impl EditorState {
-    pub fn new(size: Size, file: Option<&Path>) -> Self {
-        let filename = file
-            .and_then(Path::file_name)
-            .and_then(|name| name.to_str())
-            .unwrap_or("template.aml");
-
+    pub fn new(size: Size, project: bool) -> Self {
         Self {
             width: size.width.into(),
             height: size.height.into(),
             focused: false.into(),
-            dirty: false.into(),
-            file: filename.to_string().into(),
+            tabs: List::empty(),
+            search_status: String::new().into(),
+            regex: false.into(),
+            replace_mode: false.into(),
+            live: false.into(),
+            save_as: false.into(),
+            conflict: String::new().into(),
+            diagnostics: String::new().into(),
+            has_diagnostics: false.into(),
+            error: String::new().into(),
+            project: project.into(),
         }
     }
 }
 
-pub struct Editor {
+fn file_name(file: Option<&Path>) -> &str {
+    file.and_then(Path::file_name)
+        .and_then(|name| name.to_str())
+        .unwrap_or("template.aml")
+}
+
+pub enum EditorMessage {
+    /// Repaints the buffer, sent when the editor is shown again
+    Redraw,
+    /// Opens a template in a tab, or switches to it if it is already open
+    Open(PathBuf),
+    /// A template was renamed in the file tree
+    Renamed { from: PathBuf, to: PathBuf },
+    /// A template was deleted in the file tree
+    Deleted(PathBuf),
+    /// Saves every buffer and quits, sent from the quit confirmation
+    SaveAndQuit,
+    /// The viewport was split between the editor and the preview
+    Layout { editor: Size, preview: Size },
+}
+
+fn hash_text(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+// modification time and content hash of a file when it was last read or written
+#[derive(Clone, Copy)]
+struct DiskStamp {
+    modified: SystemTime,
+    hash: u64,
+}
+
+impl DiskStamp {
+    fn new(path: &Path, text: &str) -> Option<Self> {
+        let modified = path.metadata().and_then(|meta| meta.modified()).ok()?;
+        Some(Self {
+            modified,
+            hash: hash_text(text),
+        })
+    }
+}
+
+// a buffer that is open in a tab
+struct OpenBuffer {
     buffer: TextBuffer,
     file: Option<PathBuf>,
+    dirty: bool,
+    // hash of the text last written to the swap file
+    swapped: Option<u64>,
+    on_disk: Option<DiskStamp>,
+    // the file was changed by another program while the buffer had unsaved changes
+    conflict: bool,
+}
+
+impl OpenBuffer {
+    fn new(text: &str, file: Option<PathBuf>, size: Size) -> Self {
+        let mut buffer = TextBuffer::from_iter(text.chars(), size.width, size.height);
+        buffer.highlight_all();
+        Self {
+            buffer,
+            file,
+            dirty: false,
+            swapped: None,
+            on_disk: None,
+            conflict: false,
+        }
+    }
+
+    fn scratch(size: Size) -> Self {
+        Self::new("vstack\n", None, size)
+    }
+
+    fn open(path: PathBuf, size: Size) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(&path)?;
+        let mut open = Self::new(&text, Some(path.clone()), size);
+        open.on_disk = DiskStamp::new(&path, &text);
+        Ok(open)
+    }
+
+    // writes the buffer to its file, the swap file is not needed afterwards
+    fn save(&mut self) -> std::io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let text = self.buffer.to_string();
+        std::fs::write(path, text.as_bytes())?;
+        self.on_disk = DiskStamp::new(path, &text);
+        self.dirty = false;
+        self.conflict = false;
+        self.discard_swap();
+        Ok(())
+    }
+
+    // looks for changes made by other programs, a clean buffer is reloaded right away. Returns
+    // true if the buffer was reloaded or is in conflict now.
+    fn poll_disk(&mut self) -> bool {
+        let Some(path) = &self.file else {
+            return false;
+        };
+        if self.conflict {
+            return false;
+        }
+        let Ok(modified) = path.metadata().and_then(|meta| meta.modified()) else {
+            return false;
+        };
+        if self
+            .on_disk
+            .is_some_and(|on_disk| on_disk.modified == modified)
+        {
+            return false;
+        }
+        let Ok(text) = std::fs::read_to_string(path) else {
+            return false;
+        };
+
+        let stamp = DiskStamp {
+            modified,
+            hash: hash_text(&text),
+        };
+        // only touched, e.g. saved without changes
+        if self
+            .on_disk
+            .is_some_and(|on_disk| on_disk.hash == stamp.hash)
+        {
+            self.on_disk = Some(stamp);
+            return false;
+        }
+
+        if self.dirty {
+            self.conflict = true;
+        } else {
+            self.reload(&text, Some(stamp));
+        }
+        true
+    }
+
+    fn reload(&mut self, text: &str, stamp: Option<DiskStamp>) {
+        self.buffer.set_text(text);
+        self.on_disk = stamp;
+        self.dirty = false;
+        self.conflict = false;
+        self.discard_swap();
+    }
+
+    // either takes the version on disk, or keeps the buffer and overwrites the file on the next
+    // save
+    fn resolve_conflict(&mut self, reload: bool) -> std::io::Result<()> {
+        self.conflict = false;
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let text = std::fs::read_to_string(path)?;
+        let stamp = DiskStamp::new(path, &text);
+        if reload {
+            self.reload(&text, stamp);
+        } else {
+            self.on_disk = stamp;
+        }
+        Ok(())
+    }
+
+    // backs up unsaved changes, skipped if nothing changed since the last backup
+    fn write_swap(&mut self) {
+        let (Some(path), true) = (&self.file, self.dirty) else {
+            return;
+        };
+        let text = self.buffer.to_string();
+        let hash = hash_text(&text);
+        if self.swapped != Some(hash) && swap::write(path, &text).is_ok() {
+            self.swapped = Some(hash);
+        }
+    }
+
+    fn discard_swap(&mut self) {
+        if let Some(path) = &self.file {
+            swap::remove(path);
+        }
+        self.swapped = None;
+    }
+}
+
+// a preview that was launched and is waiting for its document to compile
+struct PendingLaunch {
+    handle: AnathemaThreadHandle,
+    // a live preview replaces the running one instead of being focused
+    live: bool,
+    // templates that could not be registered as components, see `Project::components`
+    skipped: Vec<String>,
+}
+
+pub struct Editor {
+    buffers: Vec<OpenBuffer>,
+    // index of the buffer that is shown
+    active: usize,
+    project: Option<Project>,
+    clipboard: Clipboard,
+    // set after Ctrl-W was refused because of unsaved changes, a second Ctrl-W closes anyway
+    confirm_close: bool,
+    // time since the swap files were last written
+    since_swap: Duration,
+    // time since the files were last checked for changes made by other programs
+    since_disk_check: Duration,
+    // size of the pane the preview is shown in
+    preview_size: Size,
+    preview_mode: PreviewMode,
+    // restart the preview whenever the buffer changes
+    live: bool,
+    // buffer version the live preview was launched with, and the last version seen by tick
+    live_launched: Option<u64>,
+    live_seen: Option<u64>,
+    // time since the buffer last changed, the live preview waits for a pause in typing
+    since_edit: Duration,
+    // a preview that is still compiling, see `poll_launch`
+    pending: Option<PendingLaunch>,
     // while this is >0, tick() repaints the canvas
     should_rerender: u8,
 }
 
 impl Editor {
-    pub fn new(file: Option<PathBuf>, size: Size) -> Self {
-        let lines = match &file {
-            Some(file) => {
-                &std::fs::read_to_string(file).expect("failed to read the specified path")
+    /// `recovered` replaces the contents of `file`, it is read from a swap file left behind by
+    /// a previous session.
+    pub fn new(
+        file: Option<PathBuf>,
+        recovered: Option<String>,
+        project: Option<Project>,
+        size: Size,
+        clipboard: Clipboard,
+        preview_mode: PreviewMode,
+    ) -> Self {
+        let buffer = match (file, recovered) {
+            (Some(file), Some(text)) => {
+                let on_disk = std::fs::read_to_string(&file)
+                    .ok()
+                    .and_then(|saved| DiskStamp::new(&file, &saved));
+                let mut open = OpenBuffer::new(&text, Some(file), size);
+                open.dirty = true;
+                open.on_disk = on_disk;
+                open
+            }
+            (Some(file), None) => {
+                OpenBuffer::open(file, size).expect("failed to read the specified path")
             }
-            None => "vstack\n",
+            (None, _) => OpenBuffer::scratch(size),
         };
-        let mut buffer = TextBuffer::from_iter(lines.chars(), size.width, size.height);
-        buffer.highlight_all();
 
         Self {
-            file,
-            buffer,
+            buffers: vec![buffer],
+            active: 0,
+            project,
+            clipboard,
+            confirm_close: false,
+            since_swap: Duration::ZERO,
+            since_disk_check: Duration::ZERO,
+            preview_size: size,
+            preview_mode,
+            live: false,
+            live_launched: None,
+            live_seen: None,
+            since_edit: Duration::ZERO,
+            pending: None,
             should_rerender: 3,
         }
     }
 
-    fn check_code(&mut self, mut context: Context<'_, EditorState>, dirty: &mut Value<bool>) {
+    fn current(&mut self) -> &mut OpenBuffer {
+        &mut self.buffers[self.active]
+    }
+
+    // launches the preview, it is shown by `poll_launch` once the document compiled
+    fn check_code(&mut self, context: &mut Context<'_, EditorState>, state: &mut EditorState) {
         if let Some(handle) = THREAD_HANDLE.take() {
             handle.close();
         }
+        if let Err(error) = self.launch_preview(false) {
+            report_error(context, state, error);
+        }
+    }
+
+    // restarts the live preview, the running preview is kept if the buffer fails to compile
+    fn relaunch_live(&mut self, context: &mut Context<'_, EditorState>, state: &mut EditorState) {
+        let version = self.current().buffer.version();
+        self.live_launched = Some(version);
+        if let Err(error) = self.launch_preview(true) {
+            report_error(context, state, error);
+        }
+    }
+
+    // starts compiling the current buffer at the size of the preview pane, replacing a launch
+    // that is still compiling
+    fn launch_preview(&mut self, live: bool) -> Result<(), String> {
+        if let Some(pending) = self.pending.take() {
+            pending.handle.close();
+        }
 
-        let string = self.buffer.to_string();
+        let open = &self.buffers[self.active];
+        let preview_state = match open.file.as_deref().map(PreviewState::load) {
+            Some(Ok(preview_state)) => preview_state,
+            Some(Err(error)) => return Err(error),
+            None => None,
+        };
+        let source = open.buffer.to_string();
+        let buffers: Vec<_> = self
+            .buffers
+            .iter()
+            .filter_map(|open| Some((open.file.as_deref()?, open.buffer.to_string())))
+            .collect();
+        let (components, skipped) = self
+            .project
+            .as_ref()
+            .map(|project| project.components(&buffers))
+            .unwrap_or_default();
+
+        self.should_rerender = 3;
+        let handle = launch_anathema(
+            self.preview_mode,
+            source,
+            components,
+            preview_state,
+            self.preview_size,
+        )
+        .map_err(|error| format!("Failed to start the preview: {error}"))?;
+        self.pending = Some(PendingLaunch {
+            handle,
+            live,
+            skipped,
+        });
+        Ok(())
+    }
 
+    // shows the preview once its document compiled, compile errors are shown in the diagnostics
+    // panel instead
+    fn poll_launch(&mut self, context: &mut Context<'_, EditorState>, state: &mut EditorState) {
+        let Some(result) = self
+            .pending
+            .as_mut()
+            .and_then(|pending| pending.handle.poll_ready(COMPILE_TIMEOUT))
+        else {
+            return;
+        };
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
         self.should_rerender = 3;
-        dirty.set(false);
-        match launch_threaded_anathema(string, context.viewport.size()) {
-            Err(_) => (),
-            Ok(handle) => {
-                context.publish("run", |state| &state.focused);
-                THREAD_HANDLE.set(Some(handle));
+
+        match result {
+            Ok(()) => {
+                self.clear_diagnostics(state);
+                if !pending.skipped.is_empty() {
+                    state.diagnostics.set(format!(
+                        "{} can't be used as components, names have to start with a letter or _ \
+                         and only contain letters, digits and _",
+                        pending.skipped.join(", ")
+                    ));
+                    state.has_diagnostics.set(true);
+                }
+                let previous = THREAD_HANDLE.replace(Some(pending.handle));
+                if let Some(previous) = previous {
+                    previous.close();
+                }
+                if !pending.live {
+                    state.focused.set(false);
+                    context.publish("run", |state| &state.focused);
+                }
+            }
+            // a live preview just keeps showing the last version that compiled
+            Err(error) => {
+                pending.handle.close();
+                self.show_compile_error(&error, state);
+            }
+        }
+    }
+
+    // shows `error` in the diagnostics panel, prefixed with the file it is in. That can be another
+    // component of the project, its line is marked in the gutter if the file is open.
+    fn show_compile_error(&mut self, error: &CompileError, state: &mut EditorState) {
+        let (idx, location) = match &error.component {
+            None => {
+                let file = self.buffers[self.active].file.as_deref();
+                (Some(self.active), file_name(file).to_string())
             }
+            Some(name) => match self
+                .project
+                .as_ref()
+                .and_then(|project| project.file_of(name))
+            {
+                Some(file) => (
+                    self.buffers
+                        .iter()
+                        .position(|open| open.file.as_deref() == Some(file)),
+                    file_name(Some(file)).to_string(),
+                ),
+                None => (None, format!("@{name}")),
+            },
+        };
+
+        self.clear_diagnostics(state);
+        if let Some(idx) = idx {
+            self.buffers[idx]
+                .buffer
+                .set_error_line(error.line.map(|line| line.saturating_sub(1)));
+        }
+        let diagnostic = match error.line {
+            Some(_) => format!("{location}:{error}"),
+            None => format!("{location}: {error}"),
+        };
+        state.diagnostics.set(diagnostic);
+        state.has_diagnostics.set(true);
+    }
+
+    // switches to the tab of `path`, opening it first if needed
+    fn open_file(&mut self, path: PathBuf, state: &mut EditorState) -> std::io::Result<()> {
+        if let Some(idx) = self
+            .buffers
+            .iter()
+            .position(|open| open.file.as_ref() == Some(&path))
+        {
+            self.switch_to(idx, state);
+            return Ok(());
+        }
+
+        let open = OpenBuffer::open(path, state_size(state))?;
+        self.buffers.push(open);
+        self.switch_to(self.buffers.len() - 1, state);
+        Ok(())
+    }
+
+    fn switch_to(&mut self, idx: usize, state: &mut EditorState) {
+        // the launch belongs to the buffer that was active
+        if let Some(pending) = self.pending.take() {
+            pending.handle.close();
+        }
+        self.active = idx;
+        self.live_launched = None;
+        self.live_seen = None;
+        let regex = state.regex.copy_value();
+        let buffer = &mut self.current().buffer;
+        buffer.set_search_regex(regex);
+        state.search_status.set(buffer.search_status());
+        // the diagnostics name their file, so they stay valid in other tabs
+        self.sync_tabs(state);
+        self.should_rerender = 3;
+    }
+
+    // closes the current tab, the last tab is replaced with an empty buffer
+    fn close_current(&mut self, state: &mut EditorState) {
+        self.buffers.remove(self.active).discard_swap();
+        if self.buffers.is_empty() {
+            self.buffers.push(OpenBuffer::scratch(state_size(state)));
         }
+        self.switch_to(self.active.min(self.buffers.len() - 1), state);
     }
+
+    // writes the current buffer to `path` and makes it the buffer's file
+    fn save_as(&mut self, path: PathBuf) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let open = self.current();
+        let previous = std::mem::replace(&mut open.file, Some(path));
+        if let Err(error) = open.save() {
+            open.file = previous;
+            return Err(error);
+        }
+        if let Some(previous) = previous {
+            swap::remove(&previous);
+        }
+        if let Some(project) = &mut self.project {
+            _ = project.refresh();
+        }
+        Ok(())
+    }
+
+    // saves every dirty buffer, fails if one of them has no file yet
+    fn save_all(&mut self) -> Result<(), String> {
+        for open in self.buffers.iter_mut().filter(|open| open.dirty) {
+            let Some(path) = &open.file else {
+                return Err(format!(
+                    "{} has not been saved yet, use F2 to choose a path",
+                    file_name(None)
+                ));
+            };
+            let path = path.clone();
+            open.save()
+                .map_err(|error| format!("Failed to save {}: {error}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    fn clear_diagnostics(&mut self, state: &mut EditorState) {
+        for open in &mut self.buffers {
+            open.buffer.set_error_line(None);
+        }
+        state.diagnostics.set(String::new());
+        state.has_diagnostics.set(false);
+    }
+
+    // rebuilds the tab bar and the conflict prompt
+    fn sync_tabs(&self, state: &mut EditorState) {
+        UNSAVED_CHANGES.set(self.buffers.iter().any(|open| open.dirty));
+        let open = &self.buffers[self.active];
+        let conflict = if open.conflict {
+            file_name(open.file.as_deref()).to_string()
+        } else {
+            String::new()
+        };
+        state.conflict.set(conflict);
+        state.tabs.set(List::empty());
+        for (idx, open) in self.buffers.iter().enumerate() {
+            state.tabs.push_back(Tab {
+                name: file_name(open.file.as_deref()).to_string().into(),
+                dirty: open.dirty.into(),
+                active: (idx == self.active).into(),
+            });
+        }
+    }
+}
+
+// size of the text buffers, the state only has the inner size of the border
+fn state_size(state: &EditorState) -> Size {
+    Size::new(state.width.copy_value() + 2, state.height.copy_value() + 2)
 }
 
+// shows `message` in the error banner of the playground
+fn report_error(context: &mut Context<'_, EditorState>, state: &mut EditorState, message: String) {
+    state.error.set(message);
+    context.publish("error", |state| &state.error);
+}
+
+// how long the buffer has to stay unchanged before the live preview restarts
+const LIVE_DEBOUNCE: Duration = Duration::from_millis(400);
+
+// how often open files are checked for changes made by other programs
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// how often unsaved changes are backed up to swap files
+const SWAP_INTERVAL: Duration = Duration::from_secs(5);
+
+// how long a launch waits for the document to compile before showing the preview anyway
+const COMPILE_TIMEOUT: Duration = Duration::from_secs(2);
+
 thread_local!(pub static THREAD_HANDLE: RefCell<Option<AnathemaThreadHandle>> = Default::default());
+// true while the preview has focus and receives the key events
+thread_local!(pub static PREVIEW_FOCUSED: Cell<bool> = const { Cell::new(false) });
+// true if any open buffer is dirty, checked before quitting
+thread_local!(pub static UNSAVED_CHANGES: Cell<bool> = const { Cell::new(false) });
+
+// a clean exit does not need the backups, after a panic they are kept for recovery
+impl Drop for Editor {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            self.buffers.iter_mut().for_each(OpenBuffer::discard_swap);
+        }
+    }
+}
 
 impl Component for Editor {
-    type Message = ();
+    type Message = EditorMessage;
     type State = EditorState;
 
     fn message(
         &mut self,
-        _: Self::Message,
-        _: &mut Self::State,
+        message: Self::Message,
+        state: &mut Self::State,
         _: Elements<'_, '_>,
-        _: Context<'_, Self::State>,
+        mut context: Context<'_, Self::State>,
     ) {
+        match message {
+            EditorMessage::Redraw => {}
+            EditorMessage::Layout { editor, preview } => {
+                state.width.set(editor.width.saturating_sub(2));
+                state.height.set(editor.height.saturating_sub(2));
+                for open in &mut self.buffers {
+                    open.buffer.resize(editor.width, editor.height);
+                }
+                self.preview_size = preview;
+            }
+            EditorMessage::Open(path) => {
+                if let Err(error) = self.open_file(path.clone(), state) {
+                    report_error(
+                        &mut context,
+                        state,
+                        format!("Failed to open {}: {error}", path.display()),
+                    );
+                }
+            }
+            EditorMessage::Renamed { from, to } => {
+                for open in &mut self.buffers {
+                    if open.file.as_ref() == Some(&from) {
+                        open.discard_swap();
+                        open.file = Some(to.clone());
+                    }
+                }
+                self.sync_tabs(state);
+            }
+            EditorMessage::Deleted(path) => {
+                // the contents are kept, saving writes the file again
+                for open in &mut self.buffers {
+                    if open.file.as_ref() == Some(&path) {
+                        open.dirty = true;
+                    }
+                }
+                self.sync_tabs(state);
+            }
+            EditorMessage::SaveAndQuit => {
+                let result = self.save_all();
+                self.sync_tabs(state);
+                match result {
+                    Ok(()) => backend::quit(),
+                    Err(error) => report_error(&mut context, state, error),
+                }
+            }
+        }
+
+        if let Some(project) = &mut self.project {
+            _ = project.refresh();
+        }
         self.should_rerender = 3;
     }
 
@@ -106,95 +695,257 @@ impl Component for Editor {
         key: KeyEvent,
         state: &mut Self::State,
         elements: Elements<'_, '_>,
-        context: Context<'_, Self::State>,
+        mut context: Context<'_, Self::State>,
     ) {
         if !*state.focused.to_ref() || matches!(key.state, KeyState::Release) {
             return;
         }
 
-        match key.code {
-            KeyCode::Char('r') if key.ctrl => {
-                state.focused.set(false);
-                self.should_rerender = 0;
-                return self.check_code(context, &mut state.dirty);
+        // plain r and k answer the reload prompt, everything else (e.g. Ctrl-S or switching tabs)
+        // works as usual
+        let plain = !key.ctrl && !backend::alt_held();
+        let resolve = match key.code {
+            KeyCode::Char('r') if plain => Some(true),
+            KeyCode::Char('k') if plain => Some(false),
+            KeyCode::Esc => Some(false),
+            _ => None,
+        };
+        if let Some(reload) = resolve.filter(|_| self.current().conflict) {
+            if let Err(error) = self.current().resolve_conflict(reload) {
+                report_error(&mut context, state, format!("Failed to reload: {error}"));
             }
+            self.sync_tabs(state);
+            let buffer = &mut self.current().buffer;
+            state.search_status.set(buffer.search_status());
+            buffer.draw(elements, *state.focused.to_ref());
+            return;
+        }
+
+        let confirm_close = std::mem::take(&mut self.confirm_close);
+        let was_dirty = self.current().dirty;
+
+        if matches!(
+            key.code,
+            KeyCode::Home
+                | KeyCode::End
+                | KeyCode::Down
+                | KeyCode::Right
+                | KeyCode::Up
+                | KeyCode::Left
+                | KeyCode::PageDown
+                | KeyCode::PageUp
+        ) {
+            self.current()
+                .buffer
+                .update_selection(backend::shift_held());
+        }
+
+        match key.code {
+            KeyCode::Char('r') if key.ctrl => self.check_code(&mut context, state),
             KeyCode::Char('s') if key.ctrl => {
-                if let None = self
-                    .file
-                    .as_ref()
-                    .and_then(|path| std::fs::write(path, self.buffer.to_string().as_bytes()).err())
-                {
-                    state.dirty.set(false);
+                let open = self.current();
+                match open.file.clone() {
+                    Some(path) => {
+                        if let Err(error) = open.save() {
+                            report_error(
+                                &mut context,
+                                state,
+                                format!("Failed to save {}: {error}", path.display()),
+                            );
+                        }
+                    }
+                    // a scratch buffer has no file yet
+                    None => {
+                        state.save_as.set(true);
+                        context.set_focus("id", "save_as");
+                    }
+                }
+            }
+            KeyCode::F(2) => {
+                state.save_as.set(true);
+                context.set_focus("id", "save_as");
+            }
+            KeyCode::Char('o') if key.ctrl => {
+                let Some(next) = self.project.as_ref().and_then(|project| {
+                    project.cycle(
+                        self.buffers[self.active].file.as_deref(),
+                        !backend::shift_held(),
+                    )
+                }) else {
+                    return;
+                };
+                let next = next.to_path_buf();
+                if let Err(error) = self.open_file(next.clone(), state) {
+                    report_error(
+                        &mut context,
+                        state,
+                        format!("Failed to open {}: {error}", next.display()),
+                    );
+                }
+            }
+            KeyCode::Char('b') if key.ctrl => {
+                context.publish("files", |state| &state.focused);
+                return;
+            }
+            KeyCode::Char('l') if key.ctrl => {
+                self.live = !self.live;
+                self.live_launched = None;
+                self.live_seen = None;
+                state.live.set(self.live);
+                context.publish("live", |state| &state.live);
+            }
+            KeyCode::Char('t') if key.ctrl => {
+                self.buffers.push(OpenBuffer::scratch(state_size(state)));
+                self.switch_to(self.buffers.len() - 1, state);
+            }
+            KeyCode::Char('w') if key.ctrl => {
+                if was_dirty && !confirm_close {
+                    self.confirm_close = true;
+                    report_error(
+                        &mut context,
+                        state,
+                        "Unsaved changes, press Ctrl-W again to close anyway".to_string(),
+                    );
+                } else {
+                    self.close_current(state);
                 }
             }
+            KeyCode::PageDown if key.ctrl => {
+                self.switch_to((self.active + 1) % self.buffers.len(), state);
+            }
+            KeyCode::PageUp if key.ctrl => {
+                let len = self.buffers.len();
+                self.switch_to((self.active + len - 1) % len, state);
+            }
+            KeyCode::Char('n') if key.ctrl => {
+                self.current().buffer.find_next();
+            }
+            KeyCode::Char('p') if key.ctrl => {
+                self.current().buffer.find_previous();
+            }
+            KeyCode::Char('e') if key.ctrl => {
+                let regex = !state.regex.copy_value();
+                state.regex.set(regex);
+                self.current().buffer.set_search_regex(regex);
+            }
+            KeyCode::Char('f') if key.ctrl => {
+                let replace_mode = !state.replace_mode.copy_value();
+                state.replace_mode.set(replace_mode);
+            }
+            KeyCode::Esc => {
+                context.publish("dismiss", |state| &state.error);
+                self.clear_diagnostics(state);
+                let buffer = &mut self.current().buffer;
+                buffer.clear_search();
+                buffer.clear_selection();
+            }
+            KeyCode::Char('z') if key.ctrl => {
+                let open = self.current();
+                if open.buffer.undo() {
+                    open.dirty = true;
+                }
+            }
+            KeyCode::Char('y') if key.ctrl => {
+                let open = self.current();
+                if open.buffer.redo() {
+                    open.dirty = true;
+                }
+            }
+            KeyCode::Char('c') if key.ctrl => {
+                // without a selection the whole line is copied
+                let buffer = &self.buffers[self.active].buffer;
+                let text = buffer
+                    .selected_text()
+                    .unwrap_or_else(|| buffer.current_line_text());
+                self.clipboard.copy(text);
+            }
+            KeyCode::Char('x') if key.ctrl => {
+                let open = &mut self.buffers[self.active];
+                if open.buffer.selection().is_none() {
+                    open.buffer.select_line();
+                }
+                if let Some(text) = open.buffer.selected_text() {
+                    self.clipboard.copy(text);
+                }
+                if open.buffer.delete_selection() {
+                    open.dirty = true;
+                }
+            }
+            KeyCode::Char('v') if key.ctrl => {
+                let text = match backend::take_paste() {
+                    Some(text) => text,
+                    None => match self.clipboard.paste() {
+                        Some(text) => text.to_string(),
+                        None => return,
+                    },
+                };
+                let open = self.current();
+                open.dirty = true;
+                open.buffer.insert_str(&text);
+            }
             KeyCode::Char(' ') if key.ctrl => {
-                state.dirty.set(true);
-                self.buffer.insert_char(' ');
-                self.buffer.insert_char(' ');
-                self.buffer.insert_char(' ');
-                self.buffer.insert_char(' ');
-                self.buffer.highlight_current_line();
+                let open = self.current();
+                open.dirty = true;
+                open.buffer.insert_str("    ");
             }
             KeyCode::Char(c) => {
-                state.dirty.set(true);
-                self.buffer.insert_char(c);
-                self.buffer.highlight_current_line();
+                let open = self.current();
+                open.dirty = true;
+                open.buffer.insert_char(c);
+                open.buffer.highlight_current_line();
             }
             KeyCode::Enter => {
-                state.dirty.set(true);
-                self.buffer.insert_char('\n');
-                self.buffer.highlight_current_line()
+                let open = self.current();
+                open.dirty = true;
+                open.buffer.insert_char('\n');
+                open.buffer.highlight_current_line()
             }
             KeyCode::Delete => {
-                state.dirty.set(true);
-                self.buffer.remove_char_after();
-                self.buffer.highlight_current_line();
+                let open = self.current();
+                open.dirty = true;
+                open.buffer.remove_char_after();
+                open.buffer.highlight_current_line();
             }
             KeyCode::Backspace => {
-                state.dirty.set(true);
-                self.buffer.remove_char_before();
-                self.buffer.highlight_current_line();
-            }
-            KeyCode::Home if key.ctrl => self.buffer.move_to_start(),
-            KeyCode::End if key.ctrl => self.buffer.move_to_end(),
-            KeyCode::Home => self.buffer.move_to_linestart(),
-            KeyCode::End => self.buffer.move_to_lineend(),
-            KeyCode::Down => self.buffer.move_down(),
-            KeyCode::Right => self.buffer.move_right(),
-            KeyCode::Up => self.buffer.move_up(),
-            KeyCode::Left => self.buffer.move_left(),
+                let open = self.current();
+                open.dirty = true;
+                open.buffer.remove_char_before();
+                open.buffer.highlight_current_line();
+            }
+            KeyCode::Home if key.ctrl => self.current().buffer.move_to_start(),
+            KeyCode::End if key.ctrl => self.current().buffer.move_to_end(),
+            KeyCode::Home => self.current().buffer.move_to_linestart(),
+            KeyCode::End => self.current().buffer.move_to_lineend(),
+            KeyCode::Down => self.current().buffer.move_down(),
+            KeyCode::Right => self.current().buffer.move_right(),
+            KeyCode::Up => self.current().buffer.move_up(),
+            KeyCode::Left => self.current().buffer.move_left(),
             KeyCode::PageDown => {
-                self.buffer.move_down();
-                self.buffer.move_down();
-                self.buffer.move_down();
-                self.buffer.move_down();
-                self.buffer.move_down();
+                let buffer = &mut self.current().buffer;
+                buffer.move_down();
+                buffer.move_down();
+                buffer.move_down();
+                buffer.move_down();
+                buffer.move_down();
             }
             KeyCode::PageUp => {
-                self.buffer.move_up();
-                self.buffer.move_up();
-                self.buffer.move_up();
-                self.buffer.move_up();
-                self.buffer.move_up();
+                let buffer = &mut self.current().buffer;
+                buffer.move_up();
+                buffer.move_up();
+                buffer.move_up();
+                buffer.move_up();
+                buffer.move_up();
             }
 
             _ => return,
         }
 
-        self.buffer.draw(elements, *state.focused.to_ref());
-    }
-
-    fn resize(
-        &mut self,
-        state: &mut Self::State,
-        _: Elements<'_, '_>,
-        context: Context<'_, Self::State>,
-    ) {
-        let size = context.viewport.size();
-        *state.height.to_mut() = size.height - 2;
-        *state.width.to_mut() = size.width - 2;
-        self.buffer.resize(size.width, size.height);
-        self.should_rerender = 3;
+        if self.current().dirty != was_dirty {
+            self.sync_tabs(state);
+        }
+        let buffer = &mut self.current().buffer;
+        state.search_status.set(buffer.search_status());
+        buffer.draw(elements, *state.focused.to_ref());
     }
 
     fn on_focus(
@@ -204,7 +955,10 @@ impl Component for Editor {
         _: Context<'_, Self::State>,
     ) {
         state.focused.set(true);
-        self.buffer.draw(elements, *state.focused.to_ref());
+        self.sync_tabs(state);
+        self.current()
+            .buffer
+            .draw(elements, *state.focused.to_ref());
     }
 
     fn on_blur(
@@ -214,18 +968,59 @@ impl Component for Editor {
         _: Context<'_, Self::State>,
     ) {
         state.focused.set(false);
-        self.buffer.draw(elements, *state.focused.to_ref());
+        self.current()
+            .buffer
+            .draw(elements, *state.focused.to_ref());
     }
 
     fn tick(
         &mut self,
         state: &mut Self::State,
         elements: Elements<'_, '_>,
-        _: Context<'_, Self::State>,
-        _: std::time::Duration,
+        mut context: Context<'_, Self::State>,
+        dt: std::time::Duration,
     ) {
+        self.poll_launch(&mut context, state);
+
+        self.since_swap += dt;
+        if self.since_swap >= SWAP_INTERVAL {
+            self.since_swap = Duration::ZERO;
+            self.buffers.iter_mut().for_each(OpenBuffer::write_swap);
+        }
+
+        self.since_disk_check += dt;
+        if self.since_disk_check >= DISK_CHECK_INTERVAL {
+            self.since_disk_check = Duration::ZERO;
+            let changed = self
+                .buffers
+                .iter_mut()
+                .fold(false, |changed, open| open.poll_disk() || changed);
+            if changed {
+                self.sync_tabs(state);
+                state
+                    .search_status
+                    .set(self.current().buffer.search_status());
+                self.should_rerender = 3;
+            }
+        }
+
+        if self.live && !PREVIEW_FOCUSED.get() {
+            let version = self.current().buffer.version();
+            if self.live_seen != Some(version) {
+                self.live_seen = Some(version);
+                self.since_edit = Duration::ZERO;
+            } else if self.live_launched != Some(version) {
+                self.since_edit += dt;
+                if self.since_edit >= LIVE_DEBOUNCE {
+                    self.relaunch_live(&mut context, state);
+                }
+            }
+        }
+
         if self.should_rerender > 0 {
-            self.buffer.draw(elements, *state.focused.to_ref());
+            self.current()
+                .buffer
+                .draw(elements, *state.focused.to_ref());
             self.should_rerender -= 1;
         }
     }
@@ -234,12 +1029,70 @@ impl Component for Editor {
         &mut self,
         ident: &str,
         value: anathema::state::CommonVal<'_>,
-        _: &mut Self::State,
-        _: Elements<'_, '_>,
-        _: Context<'_, Self::State>,
+        state: &mut Self::State,
+        elements: Elements<'_, '_>,
+        mut context: Context<'_, Self::State>,
     ) {
-        if ident == "search" {
-            let str = value.to_common_str().as_ref();
+        match ident {
+            "save_as" => {
+                let str = value.to_common_str();
+                let name = str.as_ref().trim();
+                if name.is_empty() {
+                    return;
+                }
+                // relative paths are relative to the project, or the working directory
+                let path = match &self.project {
+                    Some(project) => project.dir().join(name),
+                    None => std::env::current_dir().unwrap_or_default().join(name),
+                };
+                if let Err(error) = self.save_as(path.clone()) {
+                    report_error(
+                        &mut context,
+                        state,
+                        format!("Failed to save {}: {error}", path.display()),
+                    );
+                    return;
+                }
+                state.save_as.set(false);
+                self.sync_tabs(state);
+                context.set_focus("id", "editor");
+                return;
+            }
+            "cancel_save_as" => {
+                state.save_as.set(false);
+                context.set_focus("id", "editor");
+                return;
+            }
+            _ => {}
         }
+
+        let open = self.current();
+        match ident {
+            "search" => {
+                let str = value.to_common_str();
+                let pattern = str.as_ref();
+                // submitting the same pattern again jumps to the next match
+                if pattern != open.buffer.search_pattern() {
+                    open.buffer.set_search(pattern);
+                }
+                open.buffer.find_next();
+            }
+            "replace" => {
+                if open.buffer.replace_current(value.to_common_str().as_ref()) {
+                    open.dirty = true;
+                }
+            }
+            "replace_all" => {
+                if open.buffer.replace_all(value.to_common_str().as_ref()) > 0 {
+                    open.dirty = true;
+                }
+            }
+            _ => return,
+        }
+
+        self.sync_tabs(state);
+        let buffer = &mut self.current().buffer;
+        state.search_status.set(buffer.search_status());
+        buffer.draw(elements, *state.focused.to_ref());
     }
 }
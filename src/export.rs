@@ -0,0 +1,527 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anathema::backend::tui::style::Attributes;
+use anathema::backend::tui::Style;
+use anathema::geometry::Size;
+use anathema::state::Color;
+
+use crate::preview_state::PreviewState;
+use crate::project::Project;
+use crate::thread_backend::{launch_anathema, Grapheme, PreviewMode, RenderedBuffer};
+
+// colours of cells that don't set their own, for the formats that can't leave it to a terminal
+const DEFAULT_FG: (u8, u8, u8) = (229, 229, 229);
+const DEFAULT_BG: (u8, u8, u8) = (0, 0, 0);
+
+// the size of a cell in an SVG, in pixels
+const CELL_WIDTH: usize = 9;
+const CELL_HEIGHT: usize = 18;
+const FONT_SIZE: usize = 15;
+
+// how long a headless export waits for the document to compile
+const COMPILE_TIMEOUT: Duration = Duration::from_secs(2);
+// a headless export is taken once the preview didn't change for this long
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy)]
+pub enum Format {
+    /// Text with ANSI escape codes, e.g. for `cat`
+    Ansi,
+    /// A standalone HTML page with the frame in a `<pre>`
+    Html,
+    Svg,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "ans" => Some(Format::Ansi),
+            "html" => Some(Format::Html),
+            "svg" => Some(Format::Svg),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Ansi => "ans",
+            Format::Html => "html",
+            Format::Svg => "svg",
+        }
+    }
+}
+
+/// Writes `buffer` to `path`, in the format that matches its extension.
+pub fn write(buffer: &RenderedBuffer, path: &Path) -> Result<(), String> {
+    let format = Format::from_path(path).ok_or_else(|| {
+        format!(
+            "Can't export to {}, use .ans, .html or .svg",
+            path.display()
+        )
+    })?;
+    std::fs::write(path, render(buffer, format))
+        .map_err(|error| format!("Failed to export to {}: {error}", path.display()))
+}
+
+pub fn render(buffer: &RenderedBuffer, format: Format) -> String {
+    match format {
+        Format::Ansi => ansi(buffer),
+        Format::Html => html(buffer),
+        Format::Svg => svg(buffer),
+    }
+}
+
+/// Renders `file` without a terminal and exports the frame once the preview settled down.
+pub fn headless(
+    file: &Path,
+    project: Option<&Project>,
+    size: Size,
+    mode: PreviewMode,
+    output: &Path,
+) -> Result<(), String> {
+    let source = std::fs::read_to_string(file)
+        .map_err(|error| format!("Failed to read {}: {error}", file.display()))?;
+    let components = project
+        .map(|project| project.components(Some(file), &source))
+        .unwrap_or_default();
+    let preview_state = PreviewState::load(file)?;
+
+    let mut handle = launch_anathema(mode, source, components, preview_state, size)
+        .map_err(|error| format!("Failed to start the preview: {error}"))?;
+    if let Err(error) = handle.wait_until_ready(COMPILE_TIMEOUT) {
+        handle.close();
+        return Err(match &error.component {
+            Some(name) => format!("Failed to compile @{name}: {error}"),
+            None => format!("Failed to compile: {error}"),
+        });
+    }
+
+    let mut frame = RenderedBuffer::create(0, 0);
+    let start = Instant::now();
+    let mut last_change = None;
+    while start.elapsed() < EXPORT_TIMEOUT {
+        match handle.get_buffer() {
+            Ok(Some(diff)) => {
+                diff.apply(&mut frame);
+                last_change = Some(Instant::now());
+            }
+            Ok(None) => {}
+            Err(()) => break,
+        }
+        if last_change.is_some_and(|changed| changed.elapsed() >= SETTLE_TIME) {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    handle.close();
+
+    if last_change.is_none() {
+        return Err("The preview did not render anything".to_string());
+    }
+    write(&frame, output)
+}
+
+// consecutive cells of a row with the same style
+struct Segment {
+    column: usize,
+    columns: usize,
+    text: String,
+    style: Style,
+}
+
+// wide graphemes get a segment of their own, so the SVG can give them the right width
+fn segments(buffer: &RenderedBuffer, y: usize) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut wide = false;
+    for x in 0..buffer.size().width {
+        let cell = buffer.get(x, y);
+        let (text, style) = match &cell.grapheme {
+            Grapheme::Continuation => continue,
+            Grapheme::Empty => (" ".to_string(), Style::new()),
+            Grapheme::Char(c) => (c.to_string(), cell.style),
+            Grapheme::Cluster(cluster) => (cluster.to_string(), cell.style),
+        };
+        let columns = usize::from(cell.width.max(1));
+
+        match segments.last_mut() {
+            Some(segment) if segment.style == style && !wide && columns == 1 => {
+                segment.text.push_str(&text);
+                segment.columns += 1;
+            }
+            _ => segments.push(Segment {
+                column: x,
+                columns,
+                text,
+                style,
+            }),
+        }
+        wide = columns > 1;
+    }
+    segments
+}
+
+fn ansi(buffer: &RenderedBuffer) -> String {
+    let mut output = String::new();
+    for y in 0..buffer.size().height {
+        for segment in segments(buffer, y) {
+            let style = &segment.style;
+            output.push_str("\x1b[0");
+            if style.attributes.contains(Attributes::BOLD) {
+                output.push_str(";1");
+            }
+            if style.attributes.contains(Attributes::ITALIC) {
+                output.push_str(";3");
+            }
+            if style.attributes.contains(Attributes::UNDERLINED) {
+                output.push_str(";4");
+            }
+            if let Some(fg) = style.fg.and_then(|color| ansi_color(color, 30)) {
+                _ = write!(output, ";{fg}");
+            }
+            if let Some(bg) = style.bg.and_then(|color| ansi_color(color, 40)) {
+                _ = write!(output, ";{bg}");
+            }
+            output.push('m');
+            output.push_str(&segment.text);
+        }
+        output.push_str("\x1b[0m\n");
+    }
+    output
+}
+
+fn html(buffer: &RenderedBuffer) -> String {
+    let mut output = String::new();
+    _ = write!(
+        output,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Anathema preview</title>\n</head>\n\
+         <body style=\"background: {}\">\n<pre style=\"color: {}; background: {}; font-family: monospace; line-height: 1.2\">",
+        hex(DEFAULT_BG),
+        hex(DEFAULT_FG),
+        hex(DEFAULT_BG),
+    );
+    for y in 0..buffer.size().height {
+        for segment in segments(buffer, y) {
+            let style = &segment.style;
+            let mut css = String::new();
+            if let Some(fg) = style.fg.and_then(rgb) {
+                _ = write!(css, "color: {};", hex(fg));
+            }
+            if let Some(bg) = style.bg.and_then(rgb) {
+                _ = write!(css, "background: {};", hex(bg));
+            }
+            if style.attributes.contains(Attributes::BOLD) {
+                css.push_str("font-weight: bold;");
+            }
+            if style.attributes.contains(Attributes::ITALIC) {
+                css.push_str("font-style: italic;");
+            }
+            if style.attributes.contains(Attributes::UNDERLINED) {
+                css.push_str("text-decoration: underline;");
+            }
+
+            if css.is_empty() {
+                output.push_str(&escape(&segment.text));
+            } else {
+                _ = write!(
+                    output,
+                    "<span style=\"{css}\">{}</span>",
+                    escape(&segment.text)
+                );
+            }
+        }
+        output.push('\n');
+    }
+    output.push_str("</pre>\n</body>\n</html>\n");
+    output
+}
+
+fn svg(buffer: &RenderedBuffer) -> String {
+    let size = buffer.size();
+    let width = size.width * CELL_WIDTH;
+    let height = size.height * CELL_HEIGHT;
+    let mut output = String::new();
+    _ = writeln!(
+        output,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\" font-family=\"monospace\" font-size=\"{FONT_SIZE}\">"
+    );
+    _ = writeln!(
+        output,
+        "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>",
+        hex(DEFAULT_BG)
+    );
+
+    for y in 0..size.height {
+        for segment in segments(buffer, y) {
+            let style = &segment.style;
+            let x = segment.column * CELL_WIDTH;
+            let width = segment.columns * CELL_WIDTH;
+            if let Some(bg) = style.bg.and_then(rgb) {
+                _ = writeln!(
+                    output,
+                    "<rect x=\"{x}\" y=\"{}\" width=\"{width}\" height=\"{CELL_HEIGHT}\" fill=\"{}\"/>",
+                    y * CELL_HEIGHT,
+                    hex(bg)
+                );
+            }
+            if segment.text.trim().is_empty() {
+                continue;
+            }
+
+            let fg = style.fg.and_then(rgb).unwrap_or(DEFAULT_FG);
+            let mut attributes = String::new();
+            if style.attributes.contains(Attributes::BOLD) {
+                attributes.push_str(" font-weight=\"bold\"");
+            }
+            if style.attributes.contains(Attributes::ITALIC) {
+                attributes.push_str(" font-style=\"italic\"");
+            }
+            if style.attributes.contains(Attributes::UNDERLINED) {
+                attributes.push_str(" text-decoration=\"underline\"");
+            }
+            // the baseline sits a little above the bottom of the cell
+            _ = writeln!(
+                output,
+                "<text x=\"{x}\" y=\"{}\" fill=\"{}\" textLength=\"{width}\" lengthAdjust=\"spacingAndGlyphs\" xml:space=\"preserve\"{attributes}>{}</text>",
+                (y + 1) * CELL_HEIGHT - CELL_HEIGHT / 4,
+                hex(fg),
+                escape(&segment.text)
+            );
+        }
+    }
+    output.push_str("</svg>\n");
+    output
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+// `base` is 30 for the foreground and 40 for the background
+fn ansi_color(color: Color, base: u8) -> Option<String> {
+    let code = match color {
+        Color::Reset => return None,
+        Color::Black => base,
+        Color::Red => base + 1,
+        Color::Green => base + 2,
+        Color::Yellow => base + 3,
+        Color::Blue => base + 4,
+        Color::Magenta => base + 5,
+        Color::Cyan => base + 6,
+        Color::Grey => base + 7,
+        Color::DarkGrey => base + 60,
+        Color::LightRed => base + 61,
+        Color::LightGreen => base + 62,
+        Color::LightYellow => base + 63,
+        Color::LightBlue => base + 64,
+        Color::LightMagenta => base + 65,
+        Color::LightCyan => base + 66,
+        Color::White => base + 67,
+        Color::Rgb(r, g, b) => return Some(format!("{};2;{r};{g};{b}", base + 8)),
+        Color::AnsiVal(value) => return Some(format!("{};5;{value}", base + 8)),
+    };
+    Some(code.to_string())
+}
+
+// the 16 named colours, in the order of their ANSI codes
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+fn rgb(color: Color) -> Option<(u8, u8, u8)> {
+    let index = match color {
+        Color::Reset => return None,
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::Grey => 7,
+        Color::DarkGrey => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::White => 15,
+        Color::Rgb(r, g, b) => return Some((r, g, b)),
+        Color::AnsiVal(value) => return Some(ansi_256(value)),
+    };
+    Some(PALETTE[index])
+}
+
+// the xterm 256 colour palette: the named colours, a 6x6x6 cube and a greyscale ramp
+fn ansi_256(value: u8) -> (u8, u8, u8) {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match value {
+        0..=15 => PALETTE[value as usize],
+        16..=231 => {
+            let value = value - 16;
+            (
+                LEVELS[(value / 36) as usize],
+                LEVELS[(value / 6 % 6) as usize],
+                LEVELS[(value % 6) as usize],
+            )
+        }
+        _ => {
+            let grey = 8 + (value - 232) * 10;
+            (grey, grey, grey)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::thread_backend::Cell;
+
+    fn row(cells: Vec<Cell>) -> RenderedBuffer {
+        let mut buffer = RenderedBuffer::create(cells.len(), 1);
+        for (x, cell) in cells.into_iter().enumerate() {
+            buffer.set_at(x, 0, cell);
+        }
+        buffer
+    }
+
+    fn wide(c: char) -> [Cell; 2] {
+        [
+            Cell {
+                grapheme: Grapheme::Char(c),
+                width: 2,
+                style: Style::new(),
+            },
+            Cell {
+                grapheme: Grapheme::Continuation,
+                width: 0,
+                style: Style::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn segments_group_cells_with_the_same_style() {
+        let mut red = Style::new();
+        red.set_fg(Color::Red);
+        let buffer = row(vec![
+            Cell::char('a', Style::new()),
+            Cell::char('b', Style::new()),
+            Cell::char('c', red),
+            Cell::empty(),
+        ]);
+
+        let segments: Vec<_> = segments(&buffer, 0)
+            .into_iter()
+            .map(|segment| (segment.column, segment.columns, segment.text))
+            .collect();
+        assert_eq!(
+            segments,
+            [
+                (0, 2, "ab".to_string()),
+                (2, 1, "c".to_string()),
+                (3, 1, " ".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn wide_graphemes_get_their_own_segment() {
+        let mut cells = vec![Cell::char('a', Style::new())];
+        cells.extend(wide('界'));
+        cells.push(Cell::char('b', Style::new()));
+        let buffer = row(cells);
+
+        let segments: Vec<_> = segments(&buffer, 0)
+            .into_iter()
+            .map(|segment| (segment.column, segment.columns, segment.text))
+            .collect();
+        assert_eq!(
+            segments,
+            [
+                (0, 1, "a".to_string()),
+                (1, 2, "界".to_string()),
+                (3, 1, "b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn clusters_are_kept_whole() {
+        let buffer = row(vec![Cell {
+            grapheme: Grapheme::Cluster("e\u{301}".into()),
+            width: 1,
+            style: Style::new(),
+        }]);
+        assert_eq!(segments(&buffer, 0)[0].text, "e\u{301}");
+    }
+
+    #[test]
+    fn ansi_256_palette() {
+        assert_eq!(ansi_256(1), PALETTE[1]);
+        assert_eq!(ansi_256(16), (0, 0, 0));
+        assert_eq!(ansi_256(196), (255, 0, 0));
+        assert_eq!(ansi_256(231), (255, 255, 255));
+        assert_eq!(ansi_256(232), (8, 8, 8));
+        assert_eq!(ansi_256(255), (238, 238, 238));
+    }
+
+    #[test]
+    fn escape_markup() {
+        assert_eq!(
+            escape("<a href=\"x\">&</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+        assert_eq!(escape("plain text"), "plain text");
+    }
+
+    #[test]
+    fn formats_from_extensions() {
+        assert!(matches!(
+            Format::from_path(Path::new("frame.ans")),
+            Some(Format::Ansi)
+        ));
+        assert!(matches!(
+            Format::from_path(Path::new("frame.html")),
+            Some(Format::Html)
+        ));
+        assert!(matches!(
+            Format::from_path(Path::new("frame.svg")),
+            Some(Format::Svg)
+        ));
+        assert!(Format::from_path(Path::new("frame.txt")).is_none());
+        assert!(Format::from_path(Path::new("frame")).is_none());
+    }
+}
@@ -0,0 +1,386 @@
+use std::path::{Path, PathBuf};
+
+use anathema::component::*;
+use anathema::prelude::Context;
+use anathema::state::List;
+use anathema::widgets::components::events::KeyState;
+
+use crate::editor::{EditorMessage, PREVIEW_FOCUSED};
+
+// lines of the panel that are not used by the file list (border, filter, count, prompt, footer)
+const RESERVED_LINES: usize = 8;
+
+#[derive(State)]
+pub struct FileEntry {
+    path: Value<String>,
+    selected: Value<bool>,
+}
+
+#[derive(State)]
+pub struct FileTreeState {
+    entries: Value<List<FileEntry>>,
+    focused: Value<bool>,
+    count: Value<String>,
+    prompt: Value<String>,
+    name: Value<String>,
+    status: Value<String>,
+}
+
+impl Default for FileTreeState {
+    fn default() -> Self {
+        Self {
+            entries: List::empty(),
+            focused: false.into(),
+            count: String::new().into(),
+            prompt: String::new().into(),
+            name: String::new().into(),
+            status: String::new().into(),
+        }
+    }
+}
+
+// what the name prompt (or the delete confirmation) is for
+enum Action {
+    Create,
+    Rename(PathBuf),
+    Delete(PathBuf),
+}
+
+/// Lists the templates under a directory and opens, creates, renames and deletes them.
+pub struct FileTree {
+    root: PathBuf,
+    editor: ComponentId<EditorMessage>,
+    // every template under `root`, relative to it
+    files: Vec<PathBuf>,
+    filter: String,
+    // indices into `files` that match the filter
+    visible: Vec<usize>,
+    // index into `visible`
+    selected: usize,
+    offset: usize,
+    height: usize,
+    action: Option<Action>,
+}
+
+impl FileTree {
+    pub fn new(root: PathBuf, editor: ComponentId<EditorMessage>) -> Self {
+        Self {
+            root,
+            editor,
+            files: Vec::new(),
+            filter: String::new(),
+            visible: Vec::new(),
+            selected: 0,
+            offset: 0,
+            height: 1,
+            action: None,
+        }
+    }
+
+    fn rescan(&mut self) {
+        self.files.clear();
+        collect_templates(&self.root, &self.root, &mut self.files);
+        self.files.sort();
+        self.apply_filter();
+    }
+
+    fn apply_filter(&mut self) {
+        let filter = self.filter.to_lowercase();
+        self.visible = self
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(_, path)| path.to_string_lossy().to_lowercase().contains(&filter))
+            .map(|(idx, _)| idx)
+            .collect();
+        self.selected = self.selected.min(self.visible.len().saturating_sub(1));
+    }
+
+    fn selected_file(&self) -> Option<&Path> {
+        self.visible
+            .get(self.selected)
+            .map(|idx| self.files[*idx].as_path())
+    }
+
+    fn select(&mut self, path: &Path) {
+        if let Some(pos) = self.visible.iter().position(|idx| self.files[*idx] == path) {
+            self.selected = pos;
+        }
+    }
+
+    fn move_selection(&mut self, by: isize) {
+        let last = self.visible.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(by).min(last);
+    }
+
+    // rebuilds the visible part of the list
+    fn sync(&mut self, state: &mut FileTreeState) {
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + self.height {
+            self.offset = self.selected + 1 - self.height;
+        }
+
+        state.entries.set(List::empty());
+        for (pos, idx) in self
+            .visible
+            .iter()
+            .enumerate()
+            .skip(self.offset)
+            .take(self.height)
+        {
+            state.entries.push_back(FileEntry {
+                path: self.files[*idx].to_string_lossy().into_owned().into(),
+                selected: (pos == self.selected).into(),
+            });
+        }
+
+        let count = if self.visible.len() == self.files.len() {
+            format!("{} templates", self.files.len())
+        } else {
+            format!("{}/{} templates", self.visible.len(), self.files.len())
+        };
+        state.count.set(count);
+    }
+
+    fn open(&self, path: &Path, context: &mut Context<'_, FileTreeState>) {
+        _ = context.emit(self.editor, EditorMessage::Open(self.root.join(path)));
+        context.publish("close", |state| &state.focused);
+    }
+
+    fn start(&mut self, action: Action, state: &mut FileTreeState) {
+        let (prompt, name) = match &action {
+            Action::Create => ("New file", String::new()),
+            Action::Rename(path) => ("Rename to", path.to_string_lossy().into_owned()),
+            Action::Delete(path) => ("Delete", format!("{}? (y/n)", path.to_string_lossy())),
+        };
+        state.prompt.set(prompt.to_string());
+        state.name.set(name);
+        state.status.set(String::new());
+        self.action = Some(action);
+    }
+
+    fn cancel(&mut self, state: &mut FileTreeState) {
+        self.action = None;
+        state.prompt.set(String::new());
+        state.name.set(String::new());
+    }
+
+    // runs the pending action, returns the template to select afterwards
+    fn finish(
+        &mut self,
+        state: &mut FileTreeState,
+        context: &mut Context<'_, FileTreeState>,
+    ) -> std::io::Result<Option<PathBuf>> {
+        let Some(action) = self.action.take() else {
+            return Ok(None);
+        };
+        let name = state.name.to_ref().trim().to_string();
+        self.cancel(state);
+        if name.is_empty() && !matches!(action, Action::Delete(_)) {
+            return Ok(None);
+        }
+
+        match action {
+            Action::Create => {
+                let path = template_path(&name);
+                let absolute = self.root.join(&path);
+                if absolute.exists() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::AlreadyExists,
+                        format!("{} already exists", path.display()),
+                    ));
+                }
+                if let Some(parent) = absolute.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&absolute, "vstack\n")?;
+                self.open(&path, context);
+                Ok(Some(path))
+            }
+            Action::Rename(from) => {
+                let to = template_path(&name);
+                let absolute = self.root.join(&to);
+                if absolute.exists() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::AlreadyExists,
+                        format!("{} already exists", to.display()),
+                    ));
+                }
+                if let Some(parent) = absolute.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::rename(self.root.join(&from), &absolute)?;
+                _ = context.emit(
+                    self.editor,
+                    EditorMessage::Renamed {
+                        from: self.root.join(from),
+                        to: absolute,
+                    },
+                );
+                Ok(Some(to))
+            }
+            Action::Delete(path) => {
+                let absolute = self.root.join(path);
+                std::fs::remove_file(&absolute)?;
+                _ = context.emit(self.editor, EditorMessage::Deleted(absolute));
+                Ok(None)
+            }
+        }
+    }
+}
+
+// `name` relative to the root, with the `.aml` extension added if it is missing
+fn template_path(name: &str) -> PathBuf {
+    let path = PathBuf::from(name);
+    if path.extension().is_some_and(|ext| ext == "aml") {
+        path
+    } else {
+        path.with_extension("aml")
+    }
+}
+
+// hidden directories and build output are skipped
+fn collect_templates(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let path = entry.path();
+        if name.starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            if name != "target" {
+                collect_templates(root, &path, files);
+            }
+        } else if path.extension().is_some_and(|ext| ext == "aml") {
+            if let Ok(path) = path.strip_prefix(root) {
+                files.push(path.to_path_buf());
+            }
+        }
+    }
+}
+
+impl Component for FileTree {
+    type State = FileTreeState;
+    type Message = ();
+
+    fn on_focus(
+        &mut self,
+        state: &mut Self::State,
+        _: Elements<'_, '_>,
+        context: Context<'_, Self::State>,
+    ) {
+        state.focused.set(true);
+        self.height = context
+            .viewport
+            .size()
+            .height
+            .saturating_sub(RESERVED_LINES)
+            .max(1);
+        self.rescan();
+        self.sync(state);
+    }
+
+    fn on_blur(
+        &mut self,
+        state: &mut Self::State,
+        _: Elements<'_, '_>,
+        _: Context<'_, Self::State>,
+    ) {
+        state.focused.set(false);
+    }
+
+    fn on_key(
+        &mut self,
+        key: KeyEvent,
+        state: &mut Self::State,
+        _: Elements<'_, '_>,
+        mut context: Context<'_, Self::State>,
+    ) {
+        if matches!(key.state, KeyState::Release) {
+            return;
+        }
+
+        // Some(true) while confirming a delete, Some(false) while typing a name
+        let pending = self
+            .action
+            .as_ref()
+            .map(|action| matches!(action, Action::Delete(_)));
+
+        match (pending, key.code) {
+            (Some(true), KeyCode::Char('y')) | (Some(false), KeyCode::Enter) => {
+                match self.finish(state, &mut context) {
+                    Ok(select) => {
+                        self.rescan();
+                        if let Some(path) = select {
+                            self.select(&path);
+                        }
+                    }
+                    Err(error) => state.status.set(error.to_string()),
+                }
+            }
+            (Some(true), _) | (Some(false), KeyCode::Esc) => self.cancel(state),
+            (Some(false), KeyCode::Char(c)) => state.name.to_mut().push(c),
+            (Some(false), KeyCode::Backspace) => {
+                state.name.to_mut().pop();
+            }
+            (Some(false), _) => return,
+
+            (None, KeyCode::Esc) => context.publish("close", |state| &state.focused),
+            (None, KeyCode::Char('b')) if key.ctrl => {
+                context.publish("close", |state| &state.focused)
+            }
+            (None, KeyCode::Enter) => {
+                if let Some(path) = self.selected_file() {
+                    self.open(path, &mut context);
+                }
+            }
+            (None, KeyCode::Char('/')) => context.set_focus("id", "filter"),
+            (None, KeyCode::Char('n')) => self.start(Action::Create, state),
+            (None, KeyCode::Char('r')) => {
+                if let Some(path) = self.selected_file() {
+                    self.start(Action::Rename(path.to_path_buf()), state);
+                }
+            }
+            (None, KeyCode::Char('d')) => {
+                if let Some(path) = self.selected_file() {
+                    self.start(Action::Delete(path.to_path_buf()), state);
+                }
+            }
+            (None, KeyCode::Up) => self.move_selection(-1),
+            (None, KeyCode::Down) => self.move_selection(1),
+            (None, KeyCode::PageUp) => self.move_selection(-(self.height as isize)),
+            (None, KeyCode::PageDown) => self.move_selection(self.height as isize),
+            (None, KeyCode::Home) => self.selected = 0,
+            (None, KeyCode::End) => self.move_selection(isize::MAX),
+            _ => return,
+        }
+
+        self.sync(state);
+    }
+
+    fn receive(
+        &mut self,
+        ident: &str,
+        value: CommonVal<'_>,
+        state: &mut Self::State,
+        _: Elements<'_, '_>,
+        mut context: Context<'_, Self::State>,
+    ) {
+        if ident == "filter" {
+            self.filter = value.to_common_str().as_ref().to_string();
+            self.selected = 0;
+            self.apply_filter();
+            self.sync(state);
+            context.set_focus("id", "file_tree");
+        }
+    }
+
+    fn accept_focus(&self) -> bool {
+        !PREVIEW_FOCUSED.get()
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+
+// maximum amount of undo steps that are kept around
+const MAX_HISTORY: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    Insert,
+    Delete,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorState {
+    pub cursor_x: usize,
+    pub cursor_y: usize,
+    pub offset_y: usize,
+}
+
+/// A single undo (or redo) step.
+///
+/// Applying it replaces `len` lines starting at `start` with `lines` and restores `cursor`.
+/// Applying a step yields the step that reverts it, so the same type is used for both stacks.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub start: usize,
+    pub len: usize,
+    pub lines: Vec<Vec<char>>,
+    pub cursor: CursorState,
+}
+
+struct PendingEdit {
+    kind: EditKind,
+    start: usize,
+    line_count: usize,
+    total_lines: usize,
+    lines: Vec<Vec<char>>,
+    cursor: CursorState,
+}
+
+#[derive(Default)]
+pub struct History {
+    // oldest step first, so dropping it when the history is full is cheap
+    undo: VecDeque<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    pending: Option<PendingEdit>,
+    // kind of the last edit and the cursor after it, used for coalescing consecutive typing
+    last_edit: Option<(EditKind, CursorState)>,
+}
+
+impl History {
+    /// Records the lines `start..start + lines.len()` before they get modified. `total_lines` is
+    /// the amount of lines in the whole buffer before the edit.
+    pub fn begin(
+        &mut self,
+        kind: EditKind,
+        start: usize,
+        lines: Vec<Vec<char>>,
+        total_lines: usize,
+        cursor: CursorState,
+    ) {
+        self.pending = Some(PendingEdit {
+            kind,
+            start,
+            line_count: lines.len(),
+            total_lines,
+            lines,
+            cursor,
+        });
+    }
+
+    /// Finishes the edit started by [`History::begin`]. `coalesce` is false if the edit should
+    /// always start a new undo step.
+    pub fn end(&mut self, total_lines: usize, cursor: CursorState, coalesce: bool) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        let len = (pending.line_count + total_lines).saturating_sub(pending.total_lines);
+        self.redo.clear();
+
+        let can_merge = coalesce
+            && pending.kind != EditKind::Other
+            && matches!(self.last_edit, Some((kind, after)) if kind == pending.kind && after == pending.cursor);
+
+        if let Some(last) = self.undo.back_mut().filter(|_| can_merge) {
+            // the new edit has to lie within the lines produced by the last edit
+            if pending.start >= last.start
+                && pending.start + pending.line_count <= last.start + last.len
+            {
+                last.len = last.len + len - pending.line_count;
+                self.last_edit = Some((pending.kind, cursor));
+                return;
+            }
+        }
+
+        self.undo.push_back(HistoryEntry {
+            start: pending.start,
+            len,
+            lines: pending.lines,
+            cursor: pending.cursor,
+        });
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.pop_front();
+        }
+        self.last_edit = Some((pending.kind, cursor));
+    }
+
+    /// Prevents the next edit from being merged into the previous undo step
+    pub fn seal(&mut self) {
+        self.last_edit = None;
+    }
+
+    pub fn pop_undo(&mut self) -> Option<HistoryEntry> {
+        self.last_edit = None;
+        self.undo.pop_back()
+    }
+
+    pub fn pop_redo(&mut self) -> Option<HistoryEntry> {
+        self.last_edit = None;
+        self.redo.pop()
+    }
+
+    pub fn push_undo(&mut self, entry: HistoryEntry) {
+        self.undo.push_back(entry);
+    }
+
+    pub fn push_redo(&mut self, entry: HistoryEntry) {
+        self.redo.push(entry);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::text_buffer::TextBuffer;
+
+    fn type_text(buffer: &mut TextBuffer, text: &str) {
+        text.chars().for_each(|c| buffer.insert_char(c));
+    }
+
+    fn cursor() -> CursorState {
+        CursorState {
+            cursor_x: 0,
+            cursor_y: 0,
+            offset_y: 0,
+        }
+    }
+
+    #[test]
+    fn typed_word_is_undone_in_one_step() {
+        let mut buffer = TextBuffer::from_iter("".chars(), 80, 24);
+        type_text(&mut buffer, "hello");
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "");
+        assert!(!buffer.undo());
+    }
+
+    #[test]
+    fn whitespace_starts_a_new_step() {
+        let mut buffer = TextBuffer::from_iter("".chars(), 80, 24);
+        type_text(&mut buffer, "hello world");
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "hello");
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "");
+    }
+
+    #[test]
+    fn redo_restores_the_undone_step() {
+        let mut buffer = TextBuffer::from_iter("".chars(), 80, 24);
+        type_text(&mut buffer, "hello world");
+        buffer.undo();
+        assert!(buffer.redo());
+        assert_eq!(buffer.to_string(), "hello world");
+    }
+
+    #[test]
+    fn editing_after_undo_drops_the_redo_steps() {
+        let mut buffer = TextBuffer::from_iter("".chars(), 80, 24);
+        type_text(&mut buffer, "hello world");
+        buffer.undo();
+        type_text(&mut buffer, "!");
+        assert!(!buffer.redo());
+        assert_eq!(buffer.to_string(), "hello!");
+    }
+
+    #[test]
+    fn oldest_steps_are_dropped_at_the_cap() {
+        let mut history = History::default();
+        for line in 0..MAX_HISTORY + 10 {
+            history.begin(EditKind::Other, line, vec![], line, cursor());
+            history.end(line + 1, cursor(), false);
+        }
+
+        let mut steps = vec![];
+        while let Some(entry) = history.pop_undo() {
+            steps.push(entry.start);
+        }
+        assert_eq!(steps.len(), MAX_HISTORY);
+        assert_eq!(steps.last(), Some(&10));
+    }
+}
@@ -1,7 +1,8 @@
 use anathema::component::*;
 use anathema::widgets::components::events::KeyState;
 
-use crate::editor::THREAD_HANDLE;
+use crate::backend;
+use crate::editor::PREVIEW_FOCUSED;
 
 #[derive(State, Debug)]
 pub struct InputState {
@@ -22,6 +23,26 @@ impl InputState {
     }
 }
 
+// `position_x` counts chars, not bytes
+fn char_count(state: &InputState) -> usize {
+    state.input.to_ref().chars().count()
+}
+
+// the cursor, moved back onto the text if the text got shorter
+fn cursor(state: &mut InputState) -> usize {
+    let pos_x = state.position_x.copy_value().min(char_count(state));
+    state.position_x.set(pos_x);
+    pos_x
+}
+
+// the byte offset of the char at `pos_x`, or the end of the text
+fn byte_offset(input: &str, pos_x: usize) -> usize {
+    input
+        .char_indices()
+        .nth(pos_x)
+        .map_or(input.len(), |(offset, _)| offset)
+}
+
 #[derive(Default)]
 pub struct Input;
 
@@ -54,73 +75,64 @@ impl Component for Input {
         _: Elements<'_, '_>,
         mut context: anathema::prelude::Context<'_, Self::State>,
     ) {
-        if THREAD_HANDLE.with_borrow(|v| v.is_some()) || matches!(key.state, KeyState::Release) {
+        if PREVIEW_FOCUSED.get() || matches!(key.state, KeyState::Release) {
             return;
         }
 
         match key.code {
             KeyCode::Char('c') if key.ctrl => state.input.to_mut().clear(),
+            KeyCode::Char('a') if key.ctrl => context.publish("submit_all", |state| &state.input),
+            KeyCode::Char('v') if key.ctrl => {
+                // the input is a single line, so only the first line of a paste is used
+                let Some(text) = backend::take_paste() else {
+                    return;
+                };
+                let text = text.lines().next().unwrap_or_default();
+                let pos_x = cursor(state);
+                let offset = byte_offset(&state.input.to_ref(), pos_x);
+                state.input.to_mut().insert_str(offset, text);
+                state.position_x.set(pos_x + text.chars().count());
+            }
             KeyCode::Char(c) => {
-                if state.position_x.copy_value() > state.input.to_ref().len() {
-                    state.position_x.set(state.input.to_ref().len());
-                }
-                let pos_x = state.position_x.copy_value();
-                if pos_x == state.input.to_ref().len() {
-                    state.input.to_mut().push(c);
-                } else {
-                    state.input.to_mut().insert(pos_x, c);
-                }
+                let pos_x = cursor(state);
+                let offset = byte_offset(&state.input.to_ref(), pos_x);
+                state.input.to_mut().insert(offset, c);
                 state.position_x.set(pos_x + 1);
             }
             KeyCode::Backspace => {
-                if state.position_x.copy_value() > state.input.to_ref().len() {
-                    state.position_x.set(state.input.to_ref().len());
-                }
-                let pos_x = state.position_x.copy_value();
+                let pos_x = cursor(state);
                 if pos_x == 0 {
                     return;
-                } else if pos_x == state.input.to_ref().len() {
-                    _ = state.input.to_mut().pop();
-                } else {
-                    _ = state.input.to_mut().remove(pos_x - 1);
                 }
+                let offset = byte_offset(&state.input.to_ref(), pos_x - 1);
+                _ = state.input.to_mut().remove(offset);
                 state.position_x.set(pos_x - 1);
             }
             KeyCode::Delete => {
-                if state.position_x.copy_value() > state.input.to_ref().len() {
-                    state.position_x.set(state.input.to_ref().len());
-                }
-                let pos_x = state.position_x.copy_value();
-                if pos_x == state.input.to_ref().len() {
+                let pos_x = cursor(state);
+                if pos_x == char_count(state) {
                     return;
-                } else {
-                    _ = state.input.to_mut().remove(pos_x);
                 }
+                let offset = byte_offset(&state.input.to_ref(), pos_x);
+                _ = state.input.to_mut().remove(offset);
             }
             KeyCode::Left => {
-                let pos_x = state.position_x.copy_value();
-                if pos_x > state.input.to_ref().len() {
-                    state.position_x.set(state.input.to_ref().len());
-                } else if pos_x > 0 {
-                    state.position_x.set(pos_x - 1);
-                }
+                let pos_x = cursor(state);
+                state.position_x.set(pos_x.saturating_sub(1));
             }
             KeyCode::Right => {
-                let pos_x = state.position_x.copy_value();
-                if pos_x >= state.input.to_ref().len() {
-                    state.position_x.set(state.input.to_ref().len());
-                } else {
-                    state.position_x.set(pos_x + 1);
-                }
+                let pos_x = cursor(state);
+                state.position_x.set((pos_x + 1).min(char_count(state)));
             }
             KeyCode::Up => state.position_x.set(0),
-            KeyCode::Down => state.position_x.set(state.input.to_ref().len()),
+            KeyCode::Down => state.position_x.set(char_count(state)),
             KeyCode::Enter => context.publish("submit", |state| &state.input),
+            KeyCode::Esc => context.publish("cancel", |state| &state.input),
             _ => (),
         }
     }
 
     fn accept_focus(&self) -> bool {
-        THREAD_HANDLE.with_borrow(|v| v.is_none())
+        !PREVIEW_FOCUSED.get()
     }
 }
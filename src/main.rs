@@ -1,16 +1,58 @@
-use std::path::PathBuf;
+use std::cell::Cell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use anathema::geometry::Size;
+use anathema::widgets::components::events::KeyState;
 use anathema::{backend::Backend, component::*, default_widgets::Canvas, prelude::*};
-use editor::{Editor, EditorState, THREAD_HANDLE};
+use backend::PlaygroundBackend;
+use clipboard::Clipboard;
+use editor::{Editor, EditorMessage, EditorState, PREVIEW_FOCUSED, THREAD_HANDLE, UNSAVED_CHANGES};
+use export::Format;
+use file_tree::{FileTree, FileTreeState};
 use input::{Input, InputState};
-use thread_backend::AnathemaThreadHandle;
+use project::Project;
+use thread_backend::{AnathemaThreadHandle, FrameDiff, PreviewMode, RenderedBuffer, Run};
 
+mod backend;
+mod clipboard;
+mod device;
 mod editor;
+mod export;
+mod file_tree;
+mod history;
 mod input;
+mod preview_state;
+mod project;
+mod protocol;
+mod swap;
 mod text_buffer;
 mod thread_backend;
+mod worker;
 
-struct Playground(ComponentId<()>);
+struct Playground {
+    editor: ComponentId<EditorMessage>,
+    // top left corner of the preview canvas, mouse events are sent relative to it
+    preview_origin: (u16, u16),
+    split: Split,
+    // share of the viewport given to the editor when split, in percent
+    split_ratio: usize,
+    // the terminal size the preview is rendered at, it fills its pane if this is None
+    device: Option<Size>,
+    // the last custom size, offered by the size menu next to the presets
+    custom_device: Option<Size>,
+    // a preview that sends nothing for this long is abandoned, None disables the watchdog
+    watchdog: Option<Duration>,
+    // the preview's last frame, kept up to date by applying the changes it sends
+    frame: RenderedBuffer,
+    // paint the whole frame next time instead of just the changes
+    repaint: bool,
+    // how long the current error has been shown for
+    error_shown_for: Duration,
+    // the command prefix was pressed in the preview, the next key is a playground command
+    command_prefix: bool,
+}
 
 #[derive(State)]
 enum Showing {
@@ -18,11 +60,342 @@ enum Showing {
     Preview,
 }
 
+/// How the viewport is shared between the editor and the preview.
+#[derive(Clone, Copy, PartialEq)]
+enum Split {
+    // either the editor or the preview fills the viewport
+    Off,
+    SideBySide,
+    Stacked,
+}
+
+impl Split {
+    fn next(self) -> Self {
+        match self {
+            Split::Off => Split::SideBySide,
+            Split::SideBySide => Split::Stacked,
+            Split::Stacked => Split::Off,
+        }
+    }
+}
+
+// layout changes requested through the global event handler, applied on the next tick
+#[derive(Clone, Copy)]
+enum LayoutChange {
+    Cycle,
+    // moves the divider by this many percent, positive values grow the editor
+    Move(isize),
+}
+
 #[derive(State)]
 struct PlaygroundState {
     showing: Value<Showing>,
     width: Value<usize>,
     height: Value<usize>,
+    live: Value<bool>,
+    // the editor and the preview are both visible, separated by the divider
+    split: Value<bool>,
+    editor_width: Value<usize>,
+    editor_height: Value<usize>,
+    preview_x: Value<usize>,
+    preview_y: Value<usize>,
+    preview_width: Value<usize>,
+    preview_height: Value<usize>,
+    divider: Value<String>,
+    divider_x: Value<usize>,
+    divider_y: Value<usize>,
+    divider_width: Value<usize>,
+    divider_height: Value<usize>,
+    device: Value<String>,
+    device_menu: Value<bool>,
+    device_input: Value<String>,
+    export_menu: Value<bool>,
+    error: Value<String>,
+    show_error: Value<bool>,
+    // red for errors, green when reporting that something worked
+    toast_color: Value<String>,
+    show_files: Value<bool>,
+    confirm_quit: Value<bool>,
+}
+
+// how long the error banner stays visible
+const ERROR_TIMEOUT: Duration = Duration::from_secs(8);
+const DEFAULT_WATCHDOG: Duration = Duration::from_secs(5);
+
+// the divider can't be moved further than this towards either edge, in percent
+const MIN_SPLIT_RATIO: usize = 20;
+const MAX_SPLIT_RATIO: usize = 80;
+const SPLIT_STEP: isize = 5;
+
+// Ctrl and this key start a playground command while the preview is focused, every other key
+// goes to the preview
+const COMMAND_PREFIX: KeyCode = KeyCode::Char('g');
+
+// set by the global event handler when quitting has to be confirmed first
+thread_local!(static CONFIRM_QUIT: Cell<bool> = const { Cell::new(false) });
+thread_local!(static LAYOUT_CHANGE: Cell<Option<LayoutChange>> = const { Cell::new(None) });
+
+impl Playground {
+    // splits the viewport between the editor, the divider and the preview. The preview is resized
+    // to its pane rather than the whole viewport.
+    fn layout(&mut self, state: &mut PlaygroundState, ctx: &mut Context<'_, PlaygroundState>) {
+        let width = state.width.copy_value();
+        let height = state.height.copy_value();
+        let (editor, divider, preview) = match self.split {
+            Split::Off => (
+                Size::new(width, height),
+                (0, 0, Size::new(0, 0)),
+                (0, 0, Size::new(width, height)),
+            ),
+            Split::SideBySide => {
+                let editor_width = width * self.split_ratio / 100;
+                let preview_x = (editor_width + 1).min(width);
+                (
+                    Size::new(editor_width, height),
+                    (editor_width, 0, Size::new(1, height)),
+                    (preview_x, 0, Size::new(width - preview_x, height)),
+                )
+            }
+            Split::Stacked => {
+                let editor_height = height * self.split_ratio / 100;
+                let preview_y = (editor_height + 1).min(height);
+                (
+                    Size::new(width, editor_height),
+                    (0, editor_height, Size::new(width, 1)),
+                    (0, preview_y, Size::new(width, height - preview_y)),
+                )
+            }
+        };
+        let (divider_x, divider_y, divider_size) = divider;
+        let (preview_x, preview_y, preview) = preview;
+
+        self.preview_origin = (preview_x as u16, preview_y as u16);
+        state.split.set(self.split != Split::Off);
+        state.editor_width.set(editor.width);
+        state.editor_height.set(editor.height);
+        state.divider.set(match self.split {
+            Split::Stacked => "─".to_string(),
+            _ => "│".to_string(),
+        });
+        state.divider_x.set(divider_x);
+        state.divider_y.set(divider_y);
+        state.divider_width.set(divider_size.width);
+        state.divider_height.set(divider_size.height);
+        state.preview_x.set(preview_x);
+        state.preview_y.set(preview_y);
+        state.preview_width.set(preview.width);
+        state.preview_height.set(preview.height);
+        // a simulated terminal gets its own size no matter how large the pane is
+        let preview = self.device.unwrap_or(preview);
+        self.repaint = true;
+        _ = ctx.emit(self.editor, EditorMessage::Layout { editor, preview });
+        resize_preview(preview);
+    }
+
+    fn set_device(
+        &mut self,
+        device: Option<Size>,
+        state: &mut PlaygroundState,
+        ctx: &mut Context<'_, PlaygroundState>,
+    ) {
+        self.device = device;
+        state
+            .device
+            .set(device.map(device::label).unwrap_or_default());
+        self.layout(state, ctx);
+    }
+
+    fn show_toast(&mut self, state: &mut PlaygroundState, message: String, color: &str) {
+        state.error.set(message);
+        state.toast_color.set(color.to_string());
+        state.show_error.set(true);
+        self.error_shown_for = Duration::ZERO;
+    }
+
+    // keys while the export menu is open, the frame is written to the working directory
+    fn export_menu_key(&mut self, key: KeyEvent, state: &mut PlaygroundState) {
+        let format = match key.code {
+            KeyCode::Char('a') => Format::Ansi,
+            KeyCode::Char('h') => Format::Html,
+            KeyCode::Char('s') => Format::Svg,
+            KeyCode::Esc => {
+                state.export_menu.set(false);
+                return;
+            }
+            _ => return,
+        };
+        state.export_menu.set(false);
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        // exports within the same second are numbered instead of overwriting each other
+        let extension = format.extension();
+        let path = std::iter::once(PathBuf::from(format!("preview-{timestamp}.{extension}")))
+            .chain((2..).map(|n| PathBuf::from(format!("preview-{timestamp}-{n}.{extension}"))))
+            .find(|path| !path.exists())
+            .expect("the numbered paths never run out");
+        match export::write(&self.frame, &path) {
+            Ok(()) => {
+                let message = format!("Exported the preview to {}", path.display());
+                self.show_toast(state, message, "green");
+            }
+            Err(error) => self.show_toast(state, error, "red"),
+        }
+    }
+
+    // keys while the size menu is open: a letter picks a preset, a custom size is typed as WxH
+    fn device_menu_key(
+        &mut self,
+        key: KeyEvent,
+        state: &mut PlaygroundState,
+        ctx: &mut Context<'_, PlaygroundState>,
+    ) {
+        match key.code {
+            KeyCode::Esc => {}
+            KeyCode::Char('f') => self.set_device(None, state, ctx),
+            KeyCode::Char('s') => self.set_device(Some(device::PRESETS[0].into()), state, ctx),
+            KeyCode::Char('l') => self.set_device(Some(device::PRESETS[1].into()), state, ctx),
+            KeyCode::Char(c @ ('0'..='9' | 'x')) => {
+                state.device_input.to_mut().push(c);
+                return;
+            }
+            KeyCode::Backspace => {
+                state.device_input.to_mut().pop();
+                return;
+            }
+            KeyCode::Enter => {
+                let size = match device::parse(&state.device_input.to_ref()) {
+                    Ok(size) => size,
+                    Err(error) => {
+                        // the menu stays open so the size can be corrected
+                        self.show_toast(state, error, "red");
+                        return;
+                    }
+                };
+                self.custom_device = Some(size);
+                self.set_device(Some(size), state, ctx);
+            }
+            _ => return,
+        }
+        state.device_menu.set(false);
+    }
+
+    // the key after the command prefix: E exports, D changes the size, F3 and the arrow keys
+    // change the layout like they do in the editor. Pressing the prefix twice sends it to the
+    // preview.
+    fn preview_command(
+        &mut self,
+        key: KeyEvent,
+        state: &mut PlaygroundState,
+        ctx: &mut Context<'_, PlaygroundState>,
+    ) {
+        match key.code {
+            KeyCode::Char('e') => state.export_menu.set(true),
+            KeyCode::Char('d') => {
+                let custom = self.custom_device.or(self.device);
+                state
+                    .device_input
+                    .set(custom.map(device::label).unwrap_or_default());
+                state.device_menu.set(true);
+            }
+            KeyCode::F(3) => self.change_layout(LayoutChange::Cycle, state, ctx),
+            KeyCode::Left | KeyCode::Up => {
+                self.change_layout(LayoutChange::Move(-SPLIT_STEP), state, ctx)
+            }
+            KeyCode::Right | KeyCode::Down => {
+                self.change_layout(LayoutChange::Move(SPLIT_STEP), state, ctx)
+            }
+            code if key.ctrl && code == COMMAND_PREFIX => forward_key(key),
+            _ => {}
+        }
+    }
+
+    fn change_layout(
+        &mut self,
+        change: LayoutChange,
+        state: &mut PlaygroundState,
+        ctx: &mut Context<'_, PlaygroundState>,
+    ) {
+        match change {
+            LayoutChange::Cycle => {
+                self.split = self.split.next();
+                // without a pane of its own the preview can only be shown while it's focused
+                if self.split == Split::Off && !PREVIEW_FOCUSED.get() && !state.live.copy_value() {
+                    if let Some(handle) = THREAD_HANDLE.take() {
+                        handle.close();
+                    }
+                }
+            }
+            LayoutChange::Move(by) => {
+                if self.split == Split::Off {
+                    return;
+                }
+                self.split_ratio = self
+                    .split_ratio
+                    .saturating_add_signed(by)
+                    .clamp(MIN_SPLIT_RATIO, MAX_SPLIT_RATIO);
+            }
+        }
+        self.layout(state, ctx);
+    }
+
+    // stops focusing the preview, when split (or in live mode) it keeps running next to the editor
+    fn close_preview(&mut self, state: &mut PlaygroundState) {
+        if self.split == Split::Off && !state.live.copy_value() {
+            if let Some(handle) = THREAD_HANDLE.take() {
+                handle.close();
+            }
+        }
+        *state.showing.to_mut() = Showing::Editor;
+        self.command_prefix = false;
+        PREVIEW_FOCUSED.set(false);
+        backend::set_mouse_capture(false);
+    }
+}
+
+// sends `key` to the running preview, a preview that went away is closed
+fn forward_key(key: KeyEvent) {
+    THREAD_HANDLE.with_borrow_mut(|maybe_handle| {
+        if let Some(handle) = maybe_handle {
+            if handle.send_key(key).is_err() {
+                maybe_handle.take().map(AnathemaThreadHandle::close);
+            }
+        }
+    });
+}
+
+// paints `runs` with their top left corner at `origin`, cells outside of `clip` are skipped
+fn paint_runs(canvas: &mut Canvas, runs: &[Run], origin: (usize, usize), clip: Size) {
+    for run in runs {
+        if run.y >= clip.height {
+            continue;
+        }
+        for (offset, cell) in run.cells.iter().enumerate() {
+            let x = run.x + offset;
+            if x >= clip.width {
+                break;
+            }
+            let pos = ((origin.0 + x) as u16, (origin.1 + run.y) as u16);
+            // wide graphemes that would stick out of the clip are left out
+            if x + usize::from(cell.width) > clip.width {
+                canvas.erase(pos);
+            } else {
+                cell.paint(canvas, pos);
+            }
+        }
+    }
+}
+
+fn resize_preview(size: Size) {
+    THREAD_HANDLE.with_borrow_mut(|maybe_handle| {
+        if let Some(handle) = maybe_handle {
+            if let Err(_) = handle.resize(size.width as u16, size.height as u16) {
+                maybe_handle.take().map(AnathemaThreadHandle::close);
+            }
+        }
+    });
 }
 
 impl Component for Playground {
@@ -32,12 +405,35 @@ impl Component for Playground {
     fn receive(
         &mut self,
         ident: &str,
-        _: CommonVal<'_>,
+        value: CommonVal<'_>,
         state: &mut Self::State,
         mut elements: Elements<'_, '_>,
         mut ctx: Context<'_, Self::State>,
     ) {
-        if ident == "run_aml" {
+        if ident == "editor_error" {
+            self.show_toast(state, value.to_common_str().as_ref().to_string(), "red");
+        } else if ident == "dismiss_error" {
+            state.show_error.set(false);
+        } else if ident == "show_files" {
+            state.show_files.set(true);
+            ctx.set_focus("id", "file_tree");
+        } else if ident == "close_files" {
+            state.show_files.set(false);
+            ctx.set_focus("id", "editor");
+        } else if ident == "live" {
+            let live = matches!(value, CommonVal::Bool(true));
+            state.live.set(live);
+            // live mode needs the preview next to the editor
+            if live && self.split == Split::Off {
+                self.split = Split::SideBySide;
+            }
+            if !live && !PREVIEW_FOCUSED.get() {
+                if let Some(handle) = THREAD_HANDLE.take() {
+                    handle.close();
+                }
+            }
+            self.layout(state, &mut ctx);
+        } else if ident == "run_aml" {
             elements
                 .by_tag("canvas")
                 .by_attribute("id", "preview")
@@ -54,6 +450,8 @@ impl Component for Playground {
                     }
                 });
             *state.showing.to_mut() = Showing::Preview;
+            PREVIEW_FOCUSED.set(true);
+            backend::set_mouse_capture(true);
             ctx.set_focus("id", "main");
         }
     }
@@ -64,57 +462,114 @@ impl Component for Playground {
         _: Elements<'_, '_>,
         ctx: Context<'_, Self::State>,
     ) {
-        if let Some(handle) = THREAD_HANDLE.take() {
-            handle.close();
-        }
-        *state.showing.to_mut() = Showing::Editor;
-        _ = ctx.emit(self.0, ());
+        self.close_preview(state);
+        _ = ctx.emit(self.editor, EditorMessage::Redraw);
     }
 
     fn tick(
         &mut self,
-        _: &mut Self::State,
+        state: &mut Self::State,
         mut elements: Elements<'_, '_>,
-        _: Context<'_, Self::State>,
-        _: std::time::Duration,
+        mut ctx: Context<'_, Self::State>,
+        dt: Duration,
     ) {
-        let maybe_buffer = THREAD_HANDLE.with_borrow_mut(|maybe_handle| {
+        if CONFIRM_QUIT.take() {
+            state.confirm_quit.set(true);
+            ctx.set_focus("id", "main");
+        }
+
+        if let Some(change) = LAYOUT_CHANGE.take() {
+            self.change_layout(change, state, &mut ctx);
+        }
+
+        if state.show_error.copy_value() {
+            self.error_shown_for += dt;
+            if self.error_shown_for >= ERROR_TIMEOUT {
+                state.show_error.set(false);
+            }
+        }
+
+        let watchdog = self.watchdog;
+        let mut hung = false;
+        let maybe_diff = THREAD_HANDLE.with_borrow_mut(|maybe_handle| {
             if let Some(handle) = maybe_handle {
                 match handle.get_buffer() {
                     Err(_) => {
                         maybe_handle.take().map(AnathemaThreadHandle::close);
                         None
                     }
+                    Ok(None) if watchdog.is_some_and(|timeout| handle.is_hung(timeout)) => {
+                        maybe_handle.take().map(AnathemaThreadHandle::abandon);
+                        hung = true;
+                        Some(FrameDiff::full(&RenderedBuffer::message(
+                            "Hung: ",
+                            "the preview stopped responding and was abandoned",
+                        )))
+                    }
                     Ok(v) => v,
                 }
             } else {
                 None
             }
         });
-        let Some(buffer) = maybe_buffer else {
-            return;
+
+        if hung {
+            let message = format!(
+                "The preview did not respond for {}s",
+                watchdog.unwrap_or_default().as_secs()
+            );
+            self.show_toast(state, message, "red");
+        }
+
+        // after the layout changed the whole frame is painted again, not just what changed. A
+        // preview that doesn't change sends nothing, so this can't wait for the next frame.
+        let repaint = std::mem::take(&mut self.repaint);
+        let diff = match maybe_diff {
+            Some(diff) => {
+                diff.apply(&mut self.frame);
+                if repaint && !diff.is_full() {
+                    FrameDiff::full(&self.frame)
+                } else {
+                    diff
+                }
+            }
+            None if repaint => FrameDiff::full(&self.frame),
+            None => return,
         };
 
+        let device = self.device;
+        let frame = &self.frame;
         elements
             .by_tag("canvas")
             .by_attribute("id", "preview")
             .first(|element, _| {
                 let canvas_size = element.size();
-                let buffer_size = buffer.size();
                 let Some(canvas) = element.try_to::<Canvas>() else {
                     return;
                 };
 
-                for y in 0..canvas_size.height {
-                    for x in 0..canvas_size.width {
-                        if x < buffer_size.width && y < buffer_size.height {
-                            let (char, style) = buffer.get(x, y);
-                            if *char != '\0' {
-                                canvas.put(*char, *style, (x as u16, y as u16));
-                            } else {
-                                canvas.erase((x as u16, y as u16));
+                match device {
+                    Some(device) if diff.is_full() => {
+                        device::draw_framed(canvas, canvas_size, device, frame)
+                    }
+                    Some(device) => {
+                        let placement = device::Placement::new(canvas_size, device);
+                        paint_runs(
+                            canvas,
+                            diff.runs(),
+                            (placement.x, placement.y),
+                            Size::new(placement.width, placement.height),
+                        );
+                    }
+                    None => {
+                        if diff.is_full() {
+                            for y in 0..canvas_size.height as u16 {
+                                for x in 0..canvas_size.width as u16 {
+                                    canvas.erase((x, y));
+                                }
                             }
                         }
+                        paint_runs(canvas, diff.runs(), (0, 0), canvas_size);
                     }
                 }
             });
@@ -124,15 +579,113 @@ impl Component for Playground {
         &mut self,
         state: &mut Self::State,
         _: Elements<'_, '_>,
-        context: Context<'_, Self::State>,
+        mut context: Context<'_, Self::State>,
     ) {
         let size = context.viewport.size();
         *state.width.to_mut() = size.width;
         *state.height.to_mut() = size.height;
+        self.layout(state, &mut context);
+    }
+
+    fn on_key(
+        &mut self,
+        key: KeyEvent,
+        state: &mut Self::State,
+        _: Elements<'_, '_>,
+        mut context: Context<'_, Self::State>,
+    ) {
+        if state.confirm_quit.copy_value() {
+            if matches!(key.state, KeyState::Release) {
+                return;
+            }
+            match key.code {
+                KeyCode::Char('s') => {
+                    _ = context.emit(self.editor, EditorMessage::SaveAndQuit);
+                }
+                KeyCode::Char('d') => backend::quit(),
+                KeyCode::Char('c') | KeyCode::Esc => {}
+                _ => return,
+            }
+            state.confirm_quit.set(false);
+            if let Showing::Editor = *state.showing.to_ref() {
+                context.set_focus("id", "editor");
+            }
+            return;
+        }
+
+        if state.device_menu.copy_value() {
+            if !matches!(key.state, KeyState::Release) {
+                self.device_menu_key(key, state, &mut context);
+            }
+            return;
+        }
+
+        if state.export_menu.copy_value() {
+            if !matches!(key.state, KeyState::Release) {
+                self.export_menu_key(key, state);
+            }
+            return;
+        }
+
+        // the preview gets every key but Esc and the command prefix, see `preview_command`
+        if self.command_prefix {
+            if !matches!(key.state, KeyState::Release) {
+                self.command_prefix = false;
+                self.preview_command(key, state, &mut context);
+            }
+            return;
+        }
+
+        if key.ctrl && key.code == COMMAND_PREFIX {
+            if !matches!(key.state, KeyState::Release) {
+                self.command_prefix = true;
+            }
+            return;
+        }
+
+        // Esc gets back to the editor
+        if matches!(key.code, KeyCode::Esc) {
+            self.close_preview(state);
+            context.set_focus("id", "editor");
+            return;
+        }
+
+        forward_key(key);
+    }
+
+    fn on_mouse(
+        &mut self,
+        mouse: MouseEvent,
+        state: &mut Self::State,
+        _: Elements<'_, '_>,
+        _: Context<'_, Self::State>,
+    ) {
+        let (origin_x, origin_y) = self.preview_origin;
+        let (Some(x), Some(y)) = (mouse.x.checked_sub(origin_x), mouse.y.checked_sub(origin_y))
+        else {
+            return;
+        };
+        let pane = Size::new(
+            state.preview_width.copy_value(),
+            state.preview_height.copy_value(),
+        );
+        if x as usize >= pane.width || y as usize >= pane.height {
+            return;
+        }
+        // a simulated terminal is framed somewhere inside the pane
+        let (x, y) = match self.device {
+            Some(device) => {
+                match device::Placement::new(pane, device).to_device(x as usize, y as usize) {
+                    Some((x, y)) => (x as u16, y as u16),
+                    None => return,
+                }
+            }
+            None => (x, y),
+        };
 
         THREAD_HANDLE.with_borrow_mut(|maybe_handle| {
             if let Some(handle) = maybe_handle {
-                if let Err(_) = handle.resize(size.width as u16, size.height as u16) {
+                if handle.send_mouse(MouseEvent { x, y, ..mouse }).is_err() {
                     maybe_handle.take().map(AnathemaThreadHandle::close);
                 }
             }
@@ -141,11 +694,11 @@ impl Component for Playground {
 
     fn on_focus(
         &mut self,
-        _: &mut Self::State,
+        state: &mut Self::State,
         _: Elements<'_, '_>,
         mut context: Context<'_, Self::State>,
     ) {
-        if THREAD_HANDLE.with_borrow(|maybe_handle| maybe_handle.is_none()) {
+        if !PREVIEW_FOCUSED.get() && !state.confirm_quit.copy_value() {
             context.set_focus("id", "editor");
         }
     }
@@ -169,19 +722,80 @@ macro_rules! release_bundle {
 }
 
 fn main() {
+    if std::env::args().nth(1).as_deref() == Some(thread_backend::WORKER_FLAG) {
+        worker::run();
+        return;
+    }
+
     let mut current_executable =
         std::env::current_exe().expect("Failed to get path to the current executable");
     if let Ok(path) = current_executable.strip_prefix(std::env::current_dir().unwrap_or_default()) {
         current_executable = path.to_path_buf();
     }
 
-    let file = match std::env::args().skip(1).next() {
-        Some(v) if v == "-h" || v == "--help" => {
-            println!("Usage: {} [options] [path]\n", current_executable.display());
-            println!("  -h --help: Display help information\n");
-            println!("  uses a temporary file if no path was specified");
-            return;
+    let mut osc52 = false;
+    let mut preview_mode = PreviewMode::Thread;
+    let mut path = None;
+    let mut watchdog = Some(DEFAULT_WATCHDOG);
+    let mut export = None;
+    let mut export_size = Size::from(device::PRESETS[0]);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("Usage: {} [options] [path]\n", current_executable.display());
+                println!("  -h --help: Display help information");
+                println!("  --osc52: Also copy to the terminal's clipboard using OSC 52");
+                println!("  --isolate: Run the preview in a separate process");
+                println!(
+                    "  --watchdog <seconds>: Give up on a hung preview after this long (default: {}, 0: never)",
+                    DEFAULT_WATCHDOG.as_secs()
+                );
+                println!(
+                    "  --export <file>: Render the template to a .ans, .html or .svg file and exit"
+                );
+                println!("  --size <WxH>: The terminal size used by --export (default: 80x24)\n");
+                println!("  starts with an unsaved buffer if no path was specified");
+                println!(
+                    "  if path is a directory, every .aml file in it can be used as a component"
+                );
+                return;
+            }
+            "--osc52" => osc52 = true,
+            "--isolate" => preview_mode = PreviewMode::Process,
+            "--watchdog" => {
+                let Some(seconds) = args.next().and_then(|seconds| seconds.parse().ok()) else {
+                    eprintln!("--watchdog expects a number of seconds");
+                    return;
+                };
+                watchdog = (seconds > 0).then(|| Duration::from_secs(seconds));
+            }
+            "--export" => {
+                let Some(output) = args.next() else {
+                    eprintln!("--export expects the file to export to");
+                    return;
+                };
+                export = Some(PathBuf::from(output));
+            }
+            "--size" => {
+                let size = match args.next().as_deref().map(device::parse) {
+                    Some(Ok(size)) => size,
+                    Some(Err(error)) => {
+                        eprintln!("--size: {error}");
+                        return;
+                    }
+                    None => {
+                        eprintln!("--size expects a size like 80x24");
+                        return;
+                    }
+                };
+                export_size = size;
+            }
+            _ => path = Some(arg),
         }
+    }
+
+    let (file, project) = match path {
         Some(path) => {
             let path = if !path.starts_with('/') {
                 std::env::current_dir()
@@ -190,17 +804,52 @@ fn main() {
             } else {
                 PathBuf::from(path)
             };
-            if !path.exists() {
-                if let Some(parent) = path.parent() {
-                    _ = std::fs::create_dir_all(parent);
+            if path.is_dir() {
+                let mut project =
+                    Project::open(path.clone()).expect("failed to read the project directory");
+                if project.entry().is_none() {
+                    std::fs::write(path.join("main.aml"), "vstack\n")
+                        .expect("failed to create main.aml in the project directory");
+                    project = Project::open(path).expect("failed to read the project directory");
                 }
-                std::fs::write(&path, "vstack\n").expect("failed to open the specified path");
+                let entry = project.entry().map(Path::to_path_buf);
+                (entry, Some(project))
+            } else {
+                if !path.exists() {
+                    if let Some(parent) = path.parent() {
+                        _ = std::fs::create_dir_all(parent);
+                    }
+                    std::fs::write(&path, "vstack\n").expect("failed to open the specified path");
+                }
+                (Some(path), None)
             }
-            Some(path)
         }
-        _ => None,
+        _ => (None, None),
     };
 
+    if let Some(output) = export {
+        let Some(file) = &file else {
+            eprintln!("--export needs the path of the template to render");
+            std::process::exit(1);
+        };
+        match export::headless(file, project.as_ref(), export_size, preview_mode, &output) {
+            Ok(()) => println!("Exported {} to {}", file.display(), output.display()),
+            Err(error) => {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // the file tree lists the project, or the working directory when editing a single file
+    let root = match &project {
+        Some(project) => project.dir().to_path_buf(),
+        None => std::env::current_dir().expect("Failed to get the current directory"),
+    };
+
+    let recovered = file.as_deref().and_then(recover_swap);
+
     let backend = TuiBackend::builder()
         .enable_alt_screen()
         .enable_raw_mode()
@@ -213,8 +862,9 @@ fn main() {
     editor_size.height -= 2;
     let size = backend.size();
 
+    let backend = PlaygroundBackend::new(backend);
     let mut runtime = Runtime::builder(Document::new("@main [id: \"main\"]"), backend);
-    let editor_state = EditorState::new(editor_size, file.as_ref().map(PathBuf::as_path));
+    let editor_state = EditorState::new(editor_size, project.is_some());
     runtime
         .register_component(
             "input",
@@ -223,24 +873,97 @@ fn main() {
             InputState::new("Search"),
         )
         .unwrap();
+    runtime
+        .register_component(
+            "replace",
+            release_bundle!("templates/input.aml"),
+            Input,
+            InputState::new("Replace"),
+        )
+        .unwrap();
     let editor = runtime
         .register_component(
             "editor",
             release_bundle!("templates/editor.aml"),
-            Editor::new(file, editor_size),
+            Editor::new(
+                file,
+                recovered,
+                project,
+                editor_size,
+                Clipboard::new(osc52),
+                preview_mode,
+            ),
             editor_state,
         )
         .unwrap();
+    runtime
+        .register_component(
+            "save_as",
+            release_bundle!("templates/input.aml"),
+            Input,
+            InputState::new("Save as"),
+        )
+        .unwrap();
+    runtime
+        .register_component(
+            "filter",
+            release_bundle!("templates/input.aml"),
+            Input,
+            InputState::new("Filter"),
+        )
+        .unwrap();
+    runtime
+        .register_component(
+            "file_tree",
+            release_bundle!("templates/file_tree.aml"),
+            FileTree::new(root, editor),
+            FileTreeState::default(),
+        )
+        .unwrap();
 
     runtime
         .register_component(
             "main",
             release_bundle!("templates/main.aml"),
-            Playground(editor),
+            Playground {
+                editor,
+                preview_origin: (0, 0),
+                split: Split::Off,
+                split_ratio: 50,
+                device: None,
+                custom_device: None,
+                watchdog,
+                frame: RenderedBuffer::create(0, 0),
+                repaint: false,
+                error_shown_for: Duration::ZERO,
+                command_prefix: false,
+            },
             PlaygroundState {
                 showing: Showing::Editor.into(),
                 width: size.width.into(),
                 height: size.height.into(),
+                live: false.into(),
+                split: false.into(),
+                editor_width: size.width.into(),
+                editor_height: size.height.into(),
+                preview_x: 0.into(),
+                preview_y: 0.into(),
+                preview_width: size.width.into(),
+                preview_height: size.height.into(),
+                divider: String::new().into(),
+                divider_x: 0.into(),
+                divider_y: 0.into(),
+                divider_width: 0.into(),
+                divider_height: 0.into(),
+                device: String::new().into(),
+                device_menu: false.into(),
+                device_input: String::new().into(),
+                export_menu: false.into(),
+                error: String::new().into(),
+                show_error: false.into(),
+                toast_color: "red".to_string().into(),
+                show_files: false.into(),
+                confirm_quit: false.into(),
             },
         )
         .unwrap();
@@ -252,12 +975,37 @@ fn main() {
     rt.run();
 }
 
+// asks whether a swap file left behind by a crash should be recovered, this runs before the
+// terminal is switched to the alternate screen
+fn recover_swap(file: &Path) -> Option<String> {
+    let swap_path = swap::stale(file)?;
+    print!(
+        "Found unsaved changes to {} from a previous session. Recover them? [Y/n] ",
+        file.display()
+    );
+    _ = std::io::stdout().flush();
+
+    let mut answer = String::new();
+    _ = std::io::stdin().read_line(&mut answer);
+    if matches!(answer.trim(), "n" | "N" | "no") {
+        _ = std::fs::remove_file(swap_path);
+        return None;
+    }
+    std::fs::read_to_string(swap_path).ok()
+}
+
 struct GlobalEventHandler;
 
 impl GlobalEvents for GlobalEventHandler {
-    // do manual tabbing
+    // do manual tabbing, the preview handles tab itself
     fn enable_tab_navigation(&mut self) -> bool {
-        true
+        !PREVIEW_FOCUSED.get()
+    }
+
+    // Ctrl-C is used for copying, Ctrl-Q quits instead (Ctrl-Alt-Q without asking about unsaved
+    // changes)
+    fn ctrl_c_quit(&mut self) -> bool {
+        false
     }
 
     fn handle(
@@ -266,6 +1014,53 @@ impl GlobalEvents for GlobalEventHandler {
         _: &mut Elements<'_, '_>,
         _: &mut GlobalContext<'_>,
     ) -> Option<Event> {
-        Some(event)
+        // the focused preview gets every key, its playground commands go through the command
+        // prefix instead
+        if PREVIEW_FOCUSED.get() {
+            return Some(event);
+        }
+
+        match event {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('q'),
+                ctrl: true,
+                state,
+            }) => {
+                if matches!(state, KeyState::Release) {
+                    None
+                } else if backend::alt_held() || !UNSAVED_CHANGES.get() {
+                    Some(Event::Stop)
+                } else {
+                    CONFIRM_QUIT.set(true);
+                    None
+                }
+            }
+            // F3 switches between the layouts, Alt and the arrow keys move the divider
+            Event::Key(KeyEvent {
+                code: KeyCode::F(3),
+                state,
+                ..
+            }) => {
+                if !matches!(state, KeyState::Release) {
+                    LAYOUT_CHANGE.set(Some(LayoutChange::Cycle));
+                }
+                None
+            }
+            Event::Key(KeyEvent {
+                code: code @ (KeyCode::Left | KeyCode::Right | KeyCode::Up | KeyCode::Down),
+                state,
+                ..
+            }) if backend::alt_held() => {
+                if !matches!(state, KeyState::Release) {
+                    let by = match code {
+                        KeyCode::Left | KeyCode::Up => -SPLIT_STEP,
+                        _ => SPLIT_STEP,
+                    };
+                    LAYOUT_CHANGE.set(Some(LayoutChange::Move(by)));
+                }
+                None
+            }
+            event => Some(event),
+        }
     }
 }
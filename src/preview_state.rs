@@ -0,0 +1,301 @@
+use std::path::{Path, PathBuf};
+
+use anathema::component::Component;
+use anathema::state::{
+    CommonVal, List, Map, Path as StatePath, PendingValue, State, Subscriber, Value, ValueRef,
+};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+
+/// The name the previewed document is registered under when it has state, it becomes the root
+/// component of the preview so the state can be its root state.
+pub const ROOT_COMPONENT: &str = "__preview_root";
+
+/// State for the preview, read from `<name>.state.json` or `<name>.state.toml` next to the
+/// template. Every top-level key is a value of the document's root state.
+///
+/// The file is kept as JSON until the preview starts, anathema's state can't be sent to the
+/// preview thread or worker process.
+#[derive(Clone)]
+pub struct PreviewState(JsonMap<String, JsonValue>);
+
+impl PreviewState {
+    /// Loads the sidecar file of `template`. Returns `Ok(None)` if there is none.
+    pub fn load(template: &Path) -> Result<Option<Self>, String> {
+        let Some(path) = sidecar_path(template) else {
+            return Ok(None);
+        };
+        let source = std::fs::read_to_string(&path)
+            .map_err(|error| format!("Failed to read {}: {error}", path.display()))?;
+
+        let value: JsonValue = if path.extension().is_some_and(|ext| ext == "toml") {
+            toml::from_str(&source)
+                .map(toml_to_json)
+                .map_err(|error| error.to_string())
+        } else {
+            serde_json::from_str(&source).map_err(|error| error.to_string())
+        }
+        .map_err(|error| format!("Failed to parse {}: {error}", path.display()))?;
+
+        Self::from_value(value)
+            .map(Some)
+            .map_err(|error| format!("{}: {error}", path.display()))
+    }
+
+    /// Reads the state sent to a worker process, see [`PreviewState::to_json`].
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let value = serde_json::from_str(json).map_err(|error| error.to_string())?;
+        Self::from_value(value)
+    }
+
+    pub fn to_json(&self) -> String {
+        JsonValue::Object(self.0.clone()).to_string()
+    }
+
+    // the template can only refer to top-level keys that are identifiers
+    fn from_value(value: JsonValue) -> Result<Self, String> {
+        let JsonValue::Object(map) = value else {
+            return Err("the state has to be a map".to_string());
+        };
+        let invalid: Vec<&str> = map
+            .keys()
+            .map(String::as_str)
+            .filter(|key| !is_ident(key))
+            .collect();
+        if !invalid.is_empty() {
+            return Err(format!(
+                "{} can't be used in a template, names have to start with a letter or _ and only \
+                 contain letters, digits and _",
+                invalid.join(", ")
+            ));
+        }
+        Ok(Self(map))
+    }
+
+    /// Builds the root state of the previewed document
+    pub fn root(&self) -> StateValue {
+        let mut map = Map::empty();
+        for (key, value) in &self.0 {
+            map.to_mut()
+                .insert(key.clone(), StateValue::from_json(value));
+        }
+        StateValue::Map(map)
+    }
+}
+
+/// A value of the sidecar file as anathema state.
+pub enum StateValue {
+    /// Has no value, like a key that is missing
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    List(Value<List<StateValue>>),
+    Map(Value<Map<StateValue>>),
+}
+
+impl StateValue {
+    fn from_json(value: &JsonValue) -> Self {
+        match value {
+            JsonValue::Null => StateValue::Null,
+            JsonValue::Bool(b) => StateValue::Bool(*b),
+            JsonValue::Number(n) => match n.as_i64() {
+                Some(n) => StateValue::Int(n),
+                // larger than an i64, or a fraction
+                None => StateValue::Float(n.as_f64().unwrap_or_default()),
+            },
+            JsonValue::String(s) => StateValue::String(s.clone()),
+            JsonValue::Array(values) => {
+                let mut list = List::empty();
+                for value in values {
+                    list.push_back(StateValue::from_json(value));
+                }
+                StateValue::List(list)
+            }
+            JsonValue::Object(values) => {
+                let mut map = Map::empty();
+                for (key, value) in values {
+                    map.to_mut()
+                        .insert(key.clone(), StateValue::from_json(value));
+                }
+                StateValue::Map(map)
+            }
+        }
+    }
+}
+
+impl State for StateValue {
+    fn state_get(&self, path: StatePath<'_>, sub: Subscriber) -> Option<ValueRef> {
+        match self {
+            StateValue::List(list) => list.to_ref().state_get(path, sub),
+            StateValue::Map(map) => map.to_ref().state_get(path, sub),
+            _ => None,
+        }
+    }
+
+    fn state_lookup(&self, path: StatePath<'_>) -> Option<PendingValue> {
+        match self {
+            StateValue::List(list) => list.to_ref().state_lookup(path),
+            StateValue::Map(map) => map.to_ref().state_lookup(path),
+            _ => None,
+        }
+    }
+
+    fn to_common(&self) -> Option<CommonVal<'_>> {
+        match self {
+            StateValue::Null | StateValue::List(_) | StateValue::Map(_) => None,
+            StateValue::Bool(b) => Some(CommonVal::Bool(*b)),
+            StateValue::Int(n) => Some(CommonVal::Int(*n)),
+            StateValue::Float(n) => Some(CommonVal::Float(*n)),
+            StateValue::String(s) => Some(CommonVal::Str(s)),
+        }
+    }
+}
+
+/// The component the previewed document is registered as when it has state, see
+/// [`ROOT_COMPONENT`].
+pub struct PreviewRoot;
+
+impl Component for PreviewRoot {
+    type State = StateValue;
+    type Message = ();
+}
+
+// TOML datetimes have no JSON counterpart, they become strings
+fn toml_to_json(value: toml::Value) -> JsonValue {
+    match value {
+        toml::Value::String(s) => JsonValue::String(s),
+        toml::Value::Integer(n) => JsonValue::from(n),
+        // NaN and the infinities become null
+        toml::Value::Float(n) => JsonValue::from(n),
+        toml::Value::Boolean(b) => JsonValue::Bool(b),
+        toml::Value::Datetime(datetime) => JsonValue::String(datetime.to_string()),
+        toml::Value::Array(values) => values.into_iter().map(toml_to_json).collect(),
+        toml::Value::Table(table) => JsonValue::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn sidecar_path(template: &Path) -> Option<PathBuf> {
+    let stem = template.file_stem()?.to_str()?;
+    ["json", "toml"]
+        .into_iter()
+        .map(|ext| template.with_file_name(format!("{stem}.state.{ext}")))
+        .find(|path| path.is_file())
+}
+
+/// Whether `key` can be used as a name in a template
+pub(crate) fn is_ident(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // a directory of its own for every test, the tests run in parallel
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("anathema-playground-{}-{name}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // writes `source` as the sidecar of `template.aml` and loads it
+    fn load(name: &str, extension: &str, source: &str) -> Result<Option<PreviewState>, String> {
+        let dir = temp_dir(name);
+        std::fs::write(dir.join(format!("template.state.{extension}")), source).unwrap();
+        let state = PreviewState::load(&dir.join("template.aml"));
+        _ = std::fs::remove_dir_all(&dir);
+        state
+    }
+
+    #[test]
+    fn missing_sidecar_is_no_state() {
+        let dir = temp_dir("missing");
+        assert!(matches!(
+            PreviewState::load(&dir.join("template.aml")),
+            Ok(None)
+        ));
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn nested_maps_and_lists() {
+        let source = r#"{"user": {"name": "ada", "tags": ["a", {"b": [1, 2]}]}}"#;
+        let state = load("nested", "json", source).unwrap().unwrap();
+        let user = &state.0["user"];
+        assert_eq!(user["name"], "ada");
+        assert_eq!(user["tags"][1]["b"][1], 2);
+        assert!(matches!(StateValue::from_json(user), StateValue::Map(_)));
+        assert!(matches!(
+            StateValue::from_json(&user["tags"]),
+            StateValue::List(_)
+        ));
+    }
+
+    #[test]
+    fn nested_keys_need_not_be_identifiers() {
+        let state = load("nested-keys", "json", r#"{"map": {"not-an-ident": 1}}"#);
+        assert!(state.is_ok());
+    }
+
+    #[test]
+    fn top_level_keys_have_to_be_identifiers() {
+        let error = PreviewState::from_json(r#"{"ok": 1, "my-key": 2, "1st": 3}"#)
+            .err()
+            .unwrap();
+        // the keys are sorted
+        assert!(error.starts_with("1st, my-key can't"), "{error}");
+        assert!(!error.contains("ok"), "{error}");
+    }
+
+    #[test]
+    fn state_has_to_be_a_map() {
+        assert!(load("list", "json", "[1, 2]").is_err());
+    }
+
+    #[test]
+    fn toml_datetimes_become_strings() {
+        let source = "released = 1979-05-27T07:32:00Z\nday = 1979-05-27";
+        let state = load("datetime", "toml", source).unwrap().unwrap();
+        assert_eq!(state.0["released"], "1979-05-27T07:32:00Z");
+        assert_eq!(state.0["day"], "1979-05-27");
+    }
+
+    #[test]
+    fn toml_floats_and_integers() {
+        let source = "ratio = 0.5\ncount = 3\nhuge = inf";
+        let state = load("float", "toml", source).unwrap().unwrap();
+        assert!(matches!(
+            StateValue::from_json(&state.0["ratio"]),
+            StateValue::Float(n) if n == 0.5
+        ));
+        assert!(matches!(
+            StateValue::from_json(&state.0["count"]),
+            StateValue::Int(3)
+        ));
+        assert!(matches!(
+            StateValue::from_json(&state.0["huge"]),
+            StateValue::Null
+        ));
+    }
+
+    #[test]
+    fn integers_beyond_i64_become_floats() {
+        let state = PreviewState::from_json(r#"{"big": 18446744073709551615}"#).unwrap();
+        assert!(matches!(
+            StateValue::from_json(&state.0["big"]),
+            StateValue::Float(_)
+        ));
+    }
+}
@@ -0,0 +1,130 @@
+use std::path::{Path, PathBuf};
+
+use crate::preview_state::is_ident;
+
+/// A directory of templates. Every `*.aml` file in it is registered as a component named after
+/// its file stem when previewing.
+pub struct Project {
+    dir: PathBuf,
+    files: Vec<PathBuf>,
+}
+
+impl Project {
+    pub fn open(dir: PathBuf) -> std::io::Result<Self> {
+        let mut project = Self {
+            dir,
+            files: Vec::new(),
+        };
+        project.refresh()?;
+        Ok(project)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Rescans the directory for templates
+    pub fn refresh(&mut self) -> std::io::Result<()> {
+        self.files = std::fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "aml"))
+            .collect();
+        self.files.sort();
+        Ok(())
+    }
+
+    /// The file that is opened first: `main.aml` or `index.aml` if they exist, otherwise the first
+    /// template in the directory
+    pub fn entry(&self) -> Option<&Path> {
+        ["main.aml", "index.aml"]
+            .into_iter()
+            .map(|name| self.dir.join(name))
+            .find_map(|path| self.files.iter().find(|file| **file == path))
+            .or(self.files.first())
+            .map(PathBuf::as_path)
+    }
+
+    /// The file after (or before, if `forward` is false) `current`, wrapping around
+    pub fn cycle(&self, current: Option<&Path>, forward: bool) -> Option<&Path> {
+        let len = self.files.len();
+        let idx = match current.and_then(|current| self.files.iter().position(|f| f == current)) {
+            Some(idx) if forward => (idx + 1) % len,
+            Some(idx) => (idx + len - 1) % len,
+            None => 0,
+        };
+        self.files.get(idx).map(PathBuf::as_path)
+    }
+
+    /// The template registered as the component `name`
+    pub fn file_of(&self, name: &str) -> Option<&Path> {
+        self.files
+            .iter()
+            .find(|path| path.file_stem().is_some_and(|stem| stem == name))
+            .map(PathBuf::as_path)
+    }
+
+    /// Reads every template as (component name, source). Templates in `open` are read from there
+    /// instead of the disk, so unsaved changes are previewed as well.
+    ///
+    /// Templates whose stem isn't a valid component name (like `my-widget.aml`) can't be
+    /// registered, their file names are returned as the second value.
+    pub fn components(&self, open: &[(&Path, String)]) -> (Vec<(String, String)>, Vec<String>) {
+        let mut components = Vec::new();
+        let mut skipped = Vec::new();
+        for path in &self.files {
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            if !is_ident(name) {
+                skipped.extend(
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .map(String::from),
+                );
+                continue;
+            }
+            let source = match open.iter().find(|(file, _)| file == path) {
+                Some((_, source)) => source.clone(),
+                None => match std::fs::read_to_string(path) {
+                    Ok(source) => source,
+                    Err(_) => continue,
+                },
+            };
+            components.push((name.to_string(), source));
+        }
+        (components, skipped)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn components_prefer_open_buffers_and_skip_invalid_names() {
+        let dir = std::env::temp_dir().join(format!(
+            "anathema-playground-{}-project",
+            std::process::id()
+        ));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["main.aml", "card.aml", "my-widget.aml"] {
+            std::fs::write(dir.join(name), format!("text \"{name}\"")).unwrap();
+        }
+
+        let project = Project::open(dir.clone()).unwrap();
+        let card = dir.join("card.aml");
+        let (components, skipped) = project.components(&[(&card, "text \"unsaved\"".into())]);
+        _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(
+            components,
+            [
+                ("card".to_string(), "text \"unsaved\"".to_string()),
+                ("main".to_string(), "text \"main.aml\"".to_string()),
+            ]
+        );
+        assert_eq!(skipped, ["my-widget.aml"]);
+    }
+}
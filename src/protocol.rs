@@ -0,0 +1,720 @@
+//! The binary framing used to talk to a preview running in a worker process.
+//!
+//! Every message is a tag byte, followed by the length of the payload as a little endian `u32`
+//! and the payload itself. The editor writes a launch request and then [`ThreadEvent`]s to the
+//! worker's stdin, the worker answers with [`ThreadMessage`]s on its stdout.
+
+use std::io::{self, Read, Write};
+
+use anathema::backend::tui::style::Attributes;
+use anathema::backend::tui::Style;
+use anathema::component::{KeyCode, KeyEvent};
+use anathema::geometry::Size;
+use anathema::state::Color;
+use anathema::widgets::components::events::{KeyState, MouseButton, MouseEvent, MouseState};
+
+use crate::preview_state::PreviewState;
+use crate::thread_backend::{
+    Cell, CompileError, FrameDiff, Grapheme, Run, ThreadEvent, ThreadMessage,
+};
+
+// editor -> worker
+const TAG_LAUNCH: u8 = 0;
+const TAG_QUIT: u8 = 1;
+const TAG_RESIZE: u8 = 2;
+const TAG_KEY: u8 = 3;
+const TAG_MOUSE: u8 = 4;
+
+// worker -> editor
+const TAG_READY: u8 = 16;
+const TAG_FRAME: u8 = 17;
+const TAG_COMPILE_ERROR: u8 = 18;
+const TAG_HEARTBEAT: u8 = 19;
+
+// sizes above this are treated as a corrupted stream rather than allocated
+const MAX_PAYLOAD: usize = 64 * 1024 * 1024;
+
+/// What the worker needs to start the preview.
+pub struct Launch {
+    pub document: String,
+    pub components: Vec<(String, String)>,
+    pub state: Option<PreviewState>,
+    pub size: Size,
+}
+
+pub fn write_launch(writer: &mut impl Write, launch: &Launch) -> io::Result<()> {
+    let mut payload = Encoder::default();
+    payload.u16(launch.size.width as u16);
+    payload.u16(launch.size.height as u16);
+    payload.str(&launch.document);
+    payload.u32(launch.components.len() as u32);
+    for (name, template) in &launch.components {
+        payload.str(name);
+        payload.str(template);
+    }
+    // an empty string is no state, the state is always a map
+    let state = launch.state.as_ref().map(PreviewState::to_json);
+    payload.str(state.as_deref().unwrap_or_default());
+    write_frame(writer, TAG_LAUNCH, &payload.0)
+}
+
+pub fn read_launch(reader: &mut impl Read) -> io::Result<Option<Launch>> {
+    let Some((tag, payload)) = read_frame(reader)? else {
+        return Ok(None);
+    };
+    if tag != TAG_LAUNCH {
+        return Err(invalid("expected a launch request"));
+    }
+
+    let mut payload = Decoder::new(&payload);
+    let width = payload.u16()? as usize;
+    let height = payload.u16()? as usize;
+    let document = payload.str()?;
+    let count = payload.u32()?;
+    let mut components = Vec::new();
+    for _ in 0..count {
+        components.push((payload.str()?, payload.str()?));
+    }
+    let state = match payload.str()? {
+        state if state.is_empty() => None,
+        state => Some(PreviewState::from_json(&state).map_err(|error| invalid(&error))?),
+    };
+    Ok(Some(Launch {
+        document,
+        components,
+        state,
+        size: Size::new(width, height),
+    }))
+}
+
+/// Events that can't be represented (e.g. keys the preview never sees) are skipped.
+pub fn write_event(writer: &mut impl Write, event: &ThreadEvent) -> io::Result<()> {
+    let mut payload = Encoder::default();
+    let tag = match event {
+        ThreadEvent::Quit => TAG_QUIT,
+        ThreadEvent::Resize { width, height } => {
+            payload.u16(*width);
+            payload.u16(*height);
+            TAG_RESIZE
+        }
+        ThreadEvent::Key(key) => {
+            if !payload.key(key) {
+                return Ok(());
+            }
+            TAG_KEY
+        }
+        ThreadEvent::Mouse(mouse) => {
+            if !payload.mouse(mouse) {
+                return Ok(());
+            }
+            TAG_MOUSE
+        }
+    };
+    write_frame(writer, tag, &payload.0)
+}
+
+/// Returns `None` once the editor closed the pipe.
+pub fn read_event(reader: &mut impl Read) -> io::Result<Option<ThreadEvent>> {
+    let Some((tag, payload)) = read_frame(reader)? else {
+        return Ok(None);
+    };
+    let mut payload = Decoder::new(&payload);
+    let event = match tag {
+        TAG_QUIT => ThreadEvent::Quit,
+        TAG_RESIZE => ThreadEvent::Resize {
+            width: payload.u16()?,
+            height: payload.u16()?,
+        },
+        TAG_KEY => ThreadEvent::Key(payload.key()?),
+        TAG_MOUSE => ThreadEvent::Mouse(payload.mouse()?),
+        _ => return Err(invalid("unknown event")),
+    };
+    Ok(Some(event))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &ThreadMessage) -> io::Result<()> {
+    let mut payload = Encoder::default();
+    let tag = match message {
+        ThreadMessage::Ready => TAG_READY,
+        ThreadMessage::Heartbeat => TAG_HEARTBEAT,
+        ThreadMessage::Frame(diff) => {
+            let size = diff.size();
+            payload.u16(size.width as u16);
+            payload.u16(size.height as u16);
+            payload.u8(diff.is_full() as u8);
+            payload.u32(diff.runs().len() as u32);
+            for run in diff.runs() {
+                payload.u16(run.x as u16);
+                payload.u16(run.y as u16);
+                payload.u32(run.cells.len() as u32);
+                for cell in &run.cells {
+                    payload.cell(cell);
+                }
+            }
+            TAG_FRAME
+        }
+        ThreadMessage::CompileError(error) => {
+            // 0 is never a valid position, they are 1-based
+            payload.u32(error.line.unwrap_or(0) as u32);
+            payload.u32(error.column.unwrap_or(0) as u32);
+            payload.str(&error.message);
+            payload.str(error.component.as_deref().unwrap_or_default());
+            TAG_COMPILE_ERROR
+        }
+    };
+    write_frame(writer, tag, &payload.0)?;
+    writer.flush()
+}
+
+/// Returns `None` once the worker closed the pipe.
+pub fn read_message(reader: &mut impl Read) -> io::Result<Option<ThreadMessage>> {
+    let Some((tag, payload)) = read_frame(reader)? else {
+        return Ok(None);
+    };
+    let mut payload = Decoder::new(&payload);
+    let message = match tag {
+        TAG_READY => ThreadMessage::Ready,
+        TAG_HEARTBEAT => ThreadMessage::Heartbeat,
+        TAG_FRAME => {
+            let width = payload.u16()? as usize;
+            let height = payload.u16()? as usize;
+            let full = payload.u8()? != 0;
+            let count = payload.u32()?;
+            let mut runs = Vec::new();
+            for _ in 0..count {
+                let x = payload.u16()? as usize;
+                let y = payload.u16()? as usize;
+                let len = payload.u32()?;
+                let mut cells = Vec::new();
+                for _ in 0..len {
+                    cells.push(payload.cell()?);
+                }
+                runs.push(Run { x, y, cells });
+            }
+            ThreadMessage::Frame(FrameDiff::new(width, height, full, runs))
+        }
+        TAG_COMPILE_ERROR => {
+            let line = payload.u32()? as usize;
+            let column = payload.u32()? as usize;
+            let message = payload.str()?;
+            let component = payload.str()?;
+            ThreadMessage::CompileError(CompileError {
+                message,
+                line: (line > 0).then_some(line),
+                column: (column > 0).then_some(column),
+                component: (!component.is_empty()).then_some(component),
+            })
+        }
+        _ => return Err(invalid("unknown message")),
+    };
+    Ok(Some(message))
+}
+
+fn write_frame(writer: &mut impl Write, tag: u8, payload: &[u8]) -> io::Result<()> {
+    writer.write_all(&[tag])?;
+    writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+    writer.write_all(payload)
+}
+
+// `None` if the stream ended cleanly before the next frame
+fn read_frame(reader: &mut impl Read) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let mut tag = [0; 1];
+    match reader.read_exact(&mut tag) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_PAYLOAD {
+        return Err(invalid("frame too large"));
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+    Ok(Some((tag[0], payload)))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    fn color(&mut self, color: Option<Color>) {
+        let Some(color) = color else {
+            self.u8(0);
+            return;
+        };
+        match color {
+            Color::Reset => self.u8(1),
+            Color::Black => self.u8(2),
+            Color::Red => self.u8(3),
+            Color::Green => self.u8(4),
+            Color::Yellow => self.u8(5),
+            Color::Blue => self.u8(6),
+            Color::Magenta => self.u8(7),
+            Color::Cyan => self.u8(8),
+            Color::Grey => self.u8(9),
+            Color::DarkGrey => self.u8(10),
+            Color::LightRed => self.u8(11),
+            Color::LightGreen => self.u8(12),
+            Color::LightYellow => self.u8(13),
+            Color::LightBlue => self.u8(14),
+            Color::LightMagenta => self.u8(15),
+            Color::LightCyan => self.u8(16),
+            Color::White => self.u8(17),
+            Color::Rgb(r, g, b) => {
+                self.u8(18);
+                self.0.extend_from_slice(&[r, g, b]);
+            }
+            Color::AnsiVal(value) => {
+                self.u8(19);
+                self.u8(value);
+            }
+        }
+    }
+
+    fn style(&mut self, style: &Style) {
+        self.color(style.fg);
+        self.color(style.bg);
+        self.u8(style.attributes.bits());
+    }
+
+    fn cell(&mut self, cell: &Cell) {
+        match &cell.grapheme {
+            Grapheme::Empty => self.u8(0),
+            Grapheme::Char(c) => {
+                self.u8(1);
+                self.u32(*c as u32);
+            }
+            Grapheme::Cluster(cluster) => {
+                self.u8(2);
+                self.str(cluster);
+            }
+            Grapheme::Continuation => self.u8(3),
+        }
+        self.u8(cell.width);
+        self.style(&cell.style);
+    }
+
+    fn key_state(&mut self, state: KeyState) {
+        self.u8(match state {
+            KeyState::Press => 0,
+            KeyState::Repeat => 1,
+            KeyState::Release => 2,
+        });
+    }
+
+    // false if the key has no encoding
+    fn key(&mut self, key: &KeyEvent) -> bool {
+        let code = match key.code {
+            KeyCode::Char(c) => {
+                self.u8(0);
+                self.u32(c as u32);
+                None
+            }
+            KeyCode::F(n) => {
+                self.u8(1);
+                self.u8(n);
+                None
+            }
+            KeyCode::Backspace => Some(2),
+            KeyCode::Enter => Some(3),
+            KeyCode::Left => Some(4),
+            KeyCode::Right => Some(5),
+            KeyCode::Up => Some(6),
+            KeyCode::Down => Some(7),
+            KeyCode::Home => Some(8),
+            KeyCode::End => Some(9),
+            KeyCode::PageUp => Some(10),
+            KeyCode::PageDown => Some(11),
+            KeyCode::Tab => Some(12),
+            KeyCode::BackTab => Some(13),
+            KeyCode::Delete => Some(14),
+            KeyCode::Insert => Some(15),
+            KeyCode::Null => Some(16),
+            KeyCode::Esc => Some(17),
+            _ => return false,
+        };
+        if let Some(code) = code {
+            self.u8(code);
+        }
+        self.u8(key.ctrl as u8);
+        self.key_state(key.state);
+        true
+    }
+
+    // false if the mouse event has no encoding
+    fn mouse(&mut self, mouse: &MouseEvent) -> bool {
+        let (state, button) = match mouse.state {
+            MouseState::Down(button) => (0, Some(button)),
+            MouseState::Up(button) => (1, Some(button)),
+            MouseState::Drag(button) => (2, Some(button)),
+            MouseState::Move => (3, None),
+            MouseState::ScrollUp => (4, None),
+            MouseState::ScrollDown => (5, None),
+            _ => return false,
+        };
+        self.u16(mouse.x);
+        self.u16(mouse.y);
+        self.u8(state);
+        self.u8(match button {
+            Some(MouseButton::Left) | None => 0,
+            Some(MouseButton::Right) => 1,
+            Some(MouseButton::Middle) => 2,
+        });
+        self.u8(mouse.ctrl as u8);
+        true
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(invalid("truncated payload"));
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn str(&mut self) -> io::Result<String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("invalid utf-8"))
+    }
+
+    fn color(&mut self) -> io::Result<Option<Color>> {
+        let color = match self.u8()? {
+            0 => return Ok(None),
+            1 => Color::Reset,
+            2 => Color::Black,
+            3 => Color::Red,
+            4 => Color::Green,
+            5 => Color::Yellow,
+            6 => Color::Blue,
+            7 => Color::Magenta,
+            8 => Color::Cyan,
+            9 => Color::Grey,
+            10 => Color::DarkGrey,
+            11 => Color::LightRed,
+            12 => Color::LightGreen,
+            13 => Color::LightYellow,
+            14 => Color::LightBlue,
+            15 => Color::LightMagenta,
+            16 => Color::LightCyan,
+            17 => Color::White,
+            18 => {
+                let rgb = self.take(3)?;
+                Color::Rgb(rgb[0], rgb[1], rgb[2])
+            }
+            19 => Color::AnsiVal(self.u8()?),
+            _ => return Err(invalid("unknown color")),
+        };
+        Ok(Some(color))
+    }
+
+    fn style(&mut self) -> io::Result<Style> {
+        let mut style = Style::new();
+        style.fg = self.color()?;
+        style.bg = self.color()?;
+        style.attributes = Attributes::from_bits_truncate(self.u8()?);
+        Ok(style)
+    }
+
+    fn cell(&mut self) -> io::Result<Cell> {
+        let grapheme = match self.u8()? {
+            0 => Grapheme::Empty,
+            1 => {
+                Grapheme::Char(char::from_u32(self.u32()?).ok_or_else(|| invalid("invalid char"))?)
+            }
+            2 => Grapheme::Cluster(self.str()?.into_boxed_str()),
+            3 => Grapheme::Continuation,
+            _ => return Err(invalid("unknown grapheme")),
+        };
+        Ok(Cell {
+            grapheme,
+            width: self.u8()?,
+            style: self.style()?,
+        })
+    }
+
+    fn key_state(&mut self) -> io::Result<KeyState> {
+        match self.u8()? {
+            0 => Ok(KeyState::Press),
+            1 => Ok(KeyState::Repeat),
+            2 => Ok(KeyState::Release),
+            _ => Err(invalid("unknown key state")),
+        }
+    }
+
+    fn key(&mut self) -> io::Result<KeyEvent> {
+        let code = match self.u8()? {
+            0 => KeyCode::Char(char::from_u32(self.u32()?).ok_or_else(|| invalid("invalid char"))?),
+            1 => KeyCode::F(self.u8()?),
+            2 => KeyCode::Backspace,
+            3 => KeyCode::Enter,
+            4 => KeyCode::Left,
+            5 => KeyCode::Right,
+            6 => KeyCode::Up,
+            7 => KeyCode::Down,
+            8 => KeyCode::Home,
+            9 => KeyCode::End,
+            10 => KeyCode::PageUp,
+            11 => KeyCode::PageDown,
+            12 => KeyCode::Tab,
+            13 => KeyCode::BackTab,
+            14 => KeyCode::Delete,
+            15 => KeyCode::Insert,
+            16 => KeyCode::Null,
+            17 => KeyCode::Esc,
+            _ => return Err(invalid("unknown key")),
+        };
+        Ok(KeyEvent {
+            code,
+            ctrl: self.u8()? != 0,
+            state: self.key_state()?,
+        })
+    }
+
+    fn mouse(&mut self) -> io::Result<MouseEvent> {
+        let x = self.u16()?;
+        let y = self.u16()?;
+        let state = self.u8()?;
+        let button = match self.u8()? {
+            0 => MouseButton::Left,
+            1 => MouseButton::Right,
+            2 => MouseButton::Middle,
+            _ => return Err(invalid("unknown mouse button")),
+        };
+        let state = match state {
+            0 => MouseState::Down(button),
+            1 => MouseState::Up(button),
+            2 => MouseState::Drag(button),
+            3 => MouseState::Move,
+            4 => MouseState::ScrollUp,
+            5 => MouseState::ScrollDown,
+            _ => return Err(invalid("unknown mouse state")),
+        };
+        Ok(MouseEvent {
+            x,
+            y,
+            state,
+            ctrl: self.u8()? != 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::thread_backend::RenderedBuffer;
+
+    fn round_trip(message: &ThreadMessage) -> ThreadMessage {
+        let mut bytes = Vec::new();
+        write_message(&mut bytes, message).unwrap();
+        let mut reader = bytes.as_slice();
+        let message = read_message(&mut reader).unwrap().unwrap();
+        assert!(reader.is_empty());
+        message
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let mut style = Style::new();
+        style.set_fg(Color::Rgb(1, 2, 3));
+        style.set_bg(Color::AnsiVal(200));
+        let mut buffer = RenderedBuffer::create(4, 2);
+        buffer.set_at(0, 0, Cell::char('a', style));
+        buffer.set_at(
+            1,
+            0,
+            Cell {
+                grapheme: Grapheme::Cluster("e\u{301}".into()),
+                width: 1,
+                style: Style::new(),
+            },
+        );
+        buffer.set_at(
+            2,
+            1,
+            Cell {
+                grapheme: Grapheme::Char('界'),
+                width: 2,
+                style: Style::new(),
+            },
+        );
+        buffer.set_at(
+            3,
+            1,
+            Cell {
+                grapheme: Grapheme::Continuation,
+                width: 0,
+                style: Style::new(),
+            },
+        );
+        let diff = FrameDiff::between(&RenderedBuffer::create(4, 2), &buffer);
+
+        let ThreadMessage::Frame(decoded) = round_trip(&ThreadMessage::Frame(diff.clone())) else {
+            panic!("expected a frame");
+        };
+        assert_eq!(decoded.size(), diff.size());
+        assert_eq!(decoded.is_full(), diff.is_full());
+        assert_eq!(decoded.runs().len(), diff.runs().len());
+        for (decoded, run) in decoded.runs().iter().zip(diff.runs()) {
+            assert_eq!((decoded.x, decoded.y), (run.x, run.y));
+            assert!(decoded.cells == run.cells);
+        }
+    }
+
+    #[test]
+    fn long_runs_round_trip() {
+        let len = usize::from(u16::MAX) + 10;
+        let cells = vec![Cell::char('a', Style::new()); len];
+        let diff = FrameDiff::new(len, 1, true, vec![Run { x: 0, y: 0, cells }]);
+
+        let ThreadMessage::Frame(decoded) = round_trip(&ThreadMessage::Frame(diff)) else {
+            panic!("expected a frame");
+        };
+        assert_eq!(decoded.runs().len(), 1);
+        assert_eq!(decoded.runs()[0].cells.len(), len);
+    }
+
+    #[test]
+    fn compile_error_round_trip() {
+        let error = CompileError {
+            message: "unexpected token".to_string(),
+            line: Some(3),
+            column: None,
+            component: Some("header".to_string()),
+        };
+        let ThreadMessage::CompileError(decoded) = round_trip(&ThreadMessage::CompileError(error))
+        else {
+            panic!("expected a compile error");
+        };
+        assert_eq!(decoded.message, "unexpected token");
+        assert_eq!(decoded.line, Some(3));
+        assert_eq!(decoded.column, None);
+        assert_eq!(decoded.component.as_deref(), Some("header"));
+    }
+
+    #[test]
+    fn launch_round_trip() {
+        let launch = Launch {
+            document: "text \"hi\"".to_string(),
+            components: vec![("header".to_string(), "vstack".to_string())],
+            state: Some(PreviewState::from_json(r#"{"items": [1, 2]}"#).unwrap()),
+            size: Size::new(80, 24),
+        };
+        let mut bytes = Vec::new();
+        write_launch(&mut bytes, &launch).unwrap();
+        let decoded = read_launch(&mut bytes.as_slice()).unwrap().unwrap();
+
+        assert_eq!(decoded.document, launch.document);
+        assert_eq!(decoded.components, launch.components);
+        assert_eq!(
+            decoded.state.map(|state| state.to_json()),
+            launch.state.map(|state| state.to_json())
+        );
+        assert_eq!(decoded.size, launch.size);
+    }
+
+    #[test]
+    fn events_round_trip() {
+        let mut bytes = Vec::new();
+        write_event(
+            &mut bytes,
+            &ThreadEvent::Resize {
+                width: 120,
+                height: 40,
+            },
+        )
+        .unwrap();
+        let key = KeyEvent {
+            code: KeyCode::Char('ä'),
+            ctrl: true,
+            state: KeyState::Press,
+        };
+        write_event(&mut bytes, &ThreadEvent::Key(key)).unwrap();
+        write_event(&mut bytes, &ThreadEvent::Quit).unwrap();
+
+        let mut reader = bytes.as_slice();
+        assert!(matches!(
+            read_event(&mut reader).unwrap(),
+            Some(ThreadEvent::Resize {
+                width: 120,
+                height: 40
+            })
+        ));
+        assert!(matches!(
+            read_event(&mut reader).unwrap(),
+            Some(ThreadEvent::Key(KeyEvent {
+                code: KeyCode::Char('ä'),
+                ctrl: true,
+                state: KeyState::Press,
+            }))
+        ));
+        assert!(matches!(
+            read_event(&mut reader).unwrap(),
+            Some(ThreadEvent::Quit)
+        ));
+        assert!(read_event(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_frames_are_errors() {
+        let mut bytes = Vec::new();
+        write_message(&mut bytes, &ThreadMessage::Ready).unwrap();
+        write_frame(&mut bytes, TAG_HEARTBEAT, &[1, 2, 3]).unwrap();
+        bytes.truncate(bytes.len() - 1);
+
+        let mut reader = bytes.as_slice();
+        assert!(matches!(
+            read_message(&mut reader),
+            Ok(Some(ThreadMessage::Ready))
+        ));
+        assert!(read_message(&mut reader).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+
+/// The swap file of `file`: `dir/name.aml` is backed up to `dir/.name.aml.swp`
+pub fn swap_path(file: &Path) -> Option<PathBuf> {
+    let name = file.file_name()?.to_str()?;
+    Some(file.with_file_name(format!(".{name}.swp")))
+}
+
+pub fn write(file: &Path, text: &str) -> std::io::Result<()> {
+    let Some(path) = swap_path(file) else {
+        return Ok(());
+    };
+    std::fs::write(path, text.as_bytes())
+}
+
+pub fn remove(file: &Path) {
+    if let Some(path) = swap_path(file) {
+        _ = std::fs::remove_file(path);
+    }
+}
+
+/// Returns the swap file of `file` if it was written after the file was last saved, which means
+/// the playground did not exit cleanly.
+pub fn stale(file: &Path) -> Option<PathBuf> {
+    let path = swap_path(file)?;
+    let swapped = path.metadata().and_then(|meta| meta.modified()).ok()?;
+    match file.metadata().and_then(|meta| meta.modified()) {
+        Ok(saved) if saved >= swapped => None,
+        _ => Some(path),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    // `template.aml` in a directory of its own, the tests run in parallel
+    fn template(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("anathema-playground-{}-{name}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("template.aml")
+    }
+
+    fn write_at(path: &Path, modified: SystemTime) {
+        std::fs::write(path, "text").unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[test]
+    fn newer_swap_is_stale() {
+        let file = template("swap-newer");
+        let now = SystemTime::now();
+        write_at(&file, now - Duration::from_secs(60));
+        write_at(&swap_path(&file).unwrap(), now);
+        assert_eq!(stale(&file), swap_path(&file));
+        _ = std::fs::remove_dir_all(file.parent().unwrap());
+    }
+
+    #[test]
+    fn older_swap_is_not_stale() {
+        let file = template("swap-older");
+        let now = SystemTime::now();
+        write_at(&swap_path(&file).unwrap(), now - Duration::from_secs(60));
+        write_at(&file, now);
+        assert_eq!(stale(&file), None);
+        _ = std::fs::remove_dir_all(file.parent().unwrap());
+    }
+
+    #[test]
+    fn swap_of_missing_file_is_stale() {
+        let file = template("swap-missing");
+        write_at(&swap_path(&file).unwrap(), SystemTime::now());
+        assert_eq!(stale(&file), swap_path(&file));
+        _ = std::fs::remove_dir_all(file.parent().unwrap());
+    }
+
+    #[test]
+    fn no_swap_is_not_stale() {
+        let file = template("swap-none");
+        write_at(&file, SystemTime::now());
+        assert_eq!(stale(&file), None);
+        _ = std::fs::remove_dir_all(file.parent().unwrap());
+    }
+}
@@ -1,6 +1,9 @@
 use anathema::{
     backend::tui::Style, default_widgets::Canvas, geometry::Size, state::Color, widgets::Elements,
 };
+use regex::Regex;
+
+use crate::history::{CursorState, EditKind, History, HistoryEntry};
 
 pub static VALID_WIDGETS: &[&str] = &[
     "text",
@@ -72,6 +75,14 @@ pub struct TextBuffer {
     cursor_y: usize,
     width: usize,
     height: usize,
+    history: History,
+    search: Search,
+    // line of the last compile error, marked in the gutter
+    error_line: Option<usize>,
+    // (line, column) of the end of the selection that stays in place while the cursor moves
+    selection_anchor: Option<(usize, usize)>,
+    // incremented on every change to the text
+    version: u64,
 }
 
 // editing
@@ -96,14 +107,43 @@ impl TextBuffer {
             offset_y: 0,
             width,
             height,
+            history: History::default(),
+            search: Search::default(),
+            error_line: None,
+            selection_anchor: None,
+            version: 0,
         }
     }
 
+    /// Replaces the whole text, keeping the cursor, scroll position and search where possible.
+    /// The history is cleared since its entries refer to the old text.
+    pub fn set_text(&mut self, text: &str) {
+        let (line, column) = self.cursor_pos();
+        self.lines = Self::from_iter(text.chars(), self.width, self.height).lines;
+        self.history = History::default();
+        self.selection_anchor = None;
+        self.error_line = None;
+        self.version += 1;
+        self.offset_y = self.offset_y.min(self.lines.len().saturating_sub(1));
+        self.set_cursor(line, column);
+        self.refresh_search();
+        self.highlight_all();
+    }
+
     pub fn insert_char(&mut self, c: char) {
+        self.delete_selection();
         if c == '\n' {
             return self.insert_newline();
         }
 
+        let row = self.offset_y + self.cursor_y;
+        self.begin_edit(EditKind::Insert, row, 1);
+        self.insert_char_raw(c);
+        // whitespace starts a new undo step, so undoing removes one word at a time
+        self.end_edit(!c.is_whitespace());
+    }
+
+    fn insert_char_raw(&mut self, c: char) {
         if let Some(line) = self.lines.get_mut(self.offset_y + self.cursor_y) {
             if self.cursor_x < line.len() {
                 line.insert(self.cursor_x, c.into());
@@ -130,7 +170,59 @@ impl TextBuffer {
         }
     }
 
+    /// Inserts `text` at the cursor as a single undo step, replacing the selection if there is one
+    pub fn insert_str(&mut self, text: &str) {
+        self.delete_selection();
+        if text.is_empty() {
+            return;
+        }
+
+        let (line, column) = self.cursor_pos();
+        self.begin_edit(EditKind::Other, line, 1);
+
+        let mut new_lines: Vec<Vec<Cell>> = text
+            .split('\n')
+            .map(|line| line.chars().map(Cell::from).collect())
+            .collect();
+        let inserted_lines = new_lines.len();
+
+        if line >= self.lines.len() {
+            self.lines.push(vec![]);
+        }
+        let tail = self.lines[line].split_off(column);
+        let last = new_lines
+            .last_mut()
+            .expect("split always yields at least one item");
+        let end_column = if inserted_lines == 1 {
+            column + last.len()
+        } else {
+            last.len()
+        };
+        last.extend(tail);
+
+        let mut new_lines = new_lines.into_iter();
+        self.lines[line].extend(
+            new_lines
+                .next()
+                .expect("split always yields at least one item"),
+        );
+        self.lines.splice(line + 1..line + 1, new_lines);
+
+        self.set_cursor(line + inserted_lines - 1, end_column);
+        self.end_edit(false);
+        for line in line..line + inserted_lines {
+            self.highlight_line(line);
+        }
+    }
+
     fn insert_newline(&mut self) {
+        let row = self.offset_y + self.cursor_y;
+        self.begin_edit(EditKind::Insert, row, 1);
+        self.insert_newline_raw();
+        self.end_edit(false);
+    }
+
+    fn insert_newline_raw(&mut self) {
         let new_line = if let Some(line) = self.lines.get_mut(self.cursor_y + self.offset_y) {
             line.split_off(self.cursor_x)
         } else {
@@ -156,6 +248,16 @@ impl TextBuffer {
     }
 
     pub fn remove_char_after(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
+        let row = self.offset_y + self.cursor_y;
+        self.begin_edit(EditKind::Delete, row, 2);
+        self.remove_char_after_raw();
+        self.end_edit(true);
+    }
+
+    fn remove_char_after_raw(&mut self) {
         if let Some(line) = self.lines.get_mut(self.cursor_y + self.offset_y) {
             if self.cursor_x < line.len() {
                 _ = line.remove(self.cursor_x);
@@ -174,9 +276,17 @@ impl TextBuffer {
     }
 
     pub fn remove_char_before(&mut self) {
+        if self.delete_selection() {
+            return;
+        }
         if self.cursor_y + self.offset_y != 0 || self.cursor_x != 0 {
+            // the undo step has to restore the cursor from before moving left
+            let cursor = self.cursor_state();
             self.move_left();
-            self.remove_char_after();
+            let row = self.offset_y + self.cursor_y;
+            self.begin_edit_with_cursor(EditKind::Delete, row, 2, cursor);
+            self.remove_char_after_raw();
+            self.end_edit(true);
         }
     }
 
@@ -318,6 +428,502 @@ impl TextBuffer {
     }
 }
 
+// selection
+impl TextBuffer {
+    /// Returns the absolute (line, column) of the cursor
+    pub fn cursor_pos(&self) -> (usize, usize) {
+        let line = (self.offset_y + self.cursor_y).min(self.lines.len().saturating_sub(1));
+        let column = self
+            .cursor_x
+            .min(self.lines.get(line).map(Vec::len).unwrap_or_default());
+        (line, column)
+    }
+
+    /// Moves the cursor to the absolute (line, column), scrolling if it would end up off-screen
+    pub fn set_cursor(&mut self, line: usize, column: usize) {
+        let line = line.min(self.lines.len().saturating_sub(1));
+        self.cursor_x = column.min(self.lines.get(line).map(Vec::len).unwrap_or_default());
+        let height = self.height.max(1);
+        if line < self.offset_y {
+            self.offset_y = line;
+        } else if line >= self.offset_y + height {
+            self.offset_y = line + 1 - height;
+        }
+        self.cursor_y = line - self.offset_y;
+    }
+
+    /// Starts a selection at the cursor if `extend` is true and none exists yet, or drops the
+    /// selection if `extend` is false. Called before every cursor movement.
+    pub fn update_selection(&mut self, extend: bool) {
+        if !extend {
+            self.selection_anchor = None;
+        } else if self.selection_anchor.is_none() {
+            self.selection_anchor = Some(self.cursor_pos());
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection_anchor = None;
+    }
+
+    /// Returns the ordered (start, end) of the selection, or None if nothing is selected
+    pub fn selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        let anchor = self.selection_anchor?;
+        let cursor = self.cursor_pos();
+        match anchor.cmp(&cursor) {
+            std::cmp::Ordering::Equal => None,
+            std::cmp::Ordering::Less => Some((anchor, cursor)),
+            std::cmp::Ordering::Greater => Some((cursor, anchor)),
+        }
+    }
+
+    /// Removes the selected text as a single undo step. Returns false if nothing was selected.
+    pub fn delete_selection(&mut self) -> bool {
+        let Some(((start_line, start_col), (end_line, end_col))) = self.selection() else {
+            self.selection_anchor = None;
+            return false;
+        };
+        self.selection_anchor = None;
+
+        self.begin_edit(EditKind::Other, start_line, end_line - start_line + 1);
+        let end_col = end_col.min(self.lines[end_line].len());
+        let tail = self.lines[end_line].split_off(end_col);
+        self.lines.drain(start_line + 1..=end_line);
+        self.lines[start_line].truncate(start_col);
+        self.lines[start_line].extend(tail);
+        self.set_cursor(start_line, start_col);
+        self.end_edit(false);
+        self.highlight_line(start_line);
+
+        true
+    }
+
+    /// Returns the selected text, or None if nothing is selected
+    pub fn selected_text(&self) -> Option<String> {
+        let ((start_line, start_col), (end_line, end_col)) = self.selection()?;
+        let mut text = String::new();
+
+        for line in start_line..=end_line {
+            let cells = &self.lines[line];
+            let from = if line == start_line { start_col } else { 0 };
+            let to = if line == end_line {
+                end_col.min(cells.len())
+            } else {
+                cells.len()
+            };
+            text.extend(cells[from..to].iter().map(|c| c.0));
+            if line != end_line {
+                text.push('\n');
+            }
+        }
+
+        Some(text)
+    }
+
+    /// Returns the line the cursor is on, including its line break
+    pub fn current_line_text(&self) -> String {
+        let (line, _) = self.cursor_pos();
+        let mut text: String = self.lines[line].iter().map(|c| c.0).collect();
+        text.push('\n');
+        text
+    }
+
+    /// Selects the line the cursor is on, including its line break
+    pub fn select_line(&mut self) {
+        let (line, _) = self.cursor_pos();
+        self.selection_anchor = Some((line, 0));
+        if line + 1 < self.lines.len() {
+            self.set_cursor(line + 1, 0);
+        } else {
+            self.set_cursor(line, self.lines[line].len());
+        }
+    }
+
+    // range of selected columns in `line`. The end is exclusive and reaches one past the end of
+    // the line if the line break is selected as well.
+    fn selected_columns(&self, line: usize) -> Option<(usize, usize)> {
+        let ((start_line, start_col), (end_line, end_col)) = self.selection()?;
+        if line < start_line || line > end_line {
+            return None;
+        }
+        let from = if line == start_line { start_col } else { 0 };
+        let to = if line == end_line {
+            end_col
+        } else {
+            self.lines.get(line).map(Vec::len).unwrap_or_default() + 1
+        };
+        Some((from, to))
+    }
+}
+
+// searching
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SearchMatch {
+    line: usize,
+    column: usize,
+    len: usize,
+}
+
+#[derive(Default)]
+struct Search {
+    pattern: String,
+    use_regex: bool,
+    // literal patterns are escaped, None if the pattern is empty or not a valid regex
+    regex: Option<Regex>,
+    // sorted by position
+    matches: Vec<SearchMatch>,
+    current: Option<usize>,
+}
+
+// converts a byte offset in `line` to a column
+fn byte_to_column(line: &str, byte: usize) -> usize {
+    line[..byte].chars().count()
+}
+
+// replaces the matches in `line` that search would find, i.e. skipping empty ones. Returns `None`
+// if nothing was replaced, otherwise the new text and the amount of replaced matches.
+fn replace_matches(
+    regex: &Regex,
+    line: &str,
+    replacement: &str,
+    expand: bool,
+) -> Option<(String, usize)> {
+    let mut replaced = String::with_capacity(line.len());
+    let mut last_end = 0;
+    let mut count = 0;
+    for captures in regex.captures_iter(line) {
+        let m = captures.get(0).expect("group 0 is always the whole match");
+        if m.is_empty() {
+            continue;
+        }
+        replaced.push_str(&line[last_end..m.start()]);
+        if expand {
+            captures.expand(replacement, &mut replaced);
+        } else {
+            replaced.push_str(replacement);
+        }
+        last_end = m.end();
+        count += 1;
+    }
+    if count == 0 {
+        return None;
+    }
+    replaced.push_str(&line[last_end..]);
+    Some((replaced, count))
+}
+
+impl TextBuffer {
+    /// Searches for `pattern`, an empty pattern ends the search
+    pub fn set_search(&mut self, pattern: &str) {
+        self.search.pattern = pattern.to_string();
+        self.search.current = None;
+        self.compile_search();
+    }
+
+    /// Switches between literal and regex patterns
+    pub fn set_search_regex(&mut self, use_regex: bool) {
+        self.search.use_regex = use_regex;
+        self.search.current = None;
+        self.compile_search();
+    }
+
+    pub fn search_pattern(&self) -> &str {
+        &self.search.pattern
+    }
+
+    pub fn clear_search(&mut self) {
+        self.set_search("");
+    }
+
+    fn compile_search(&mut self) {
+        self.search.regex = match self.search.pattern.as_str() {
+            "" => None,
+            pattern if self.search.use_regex => Regex::new(pattern).ok(),
+            pattern => Regex::new(&regex::escape(pattern)).ok(),
+        };
+        self.refresh_search();
+    }
+
+    // recalculates the matches after the text changed
+    fn refresh_search(&mut self) {
+        self.search.matches.clear();
+        if let Some(regex) = &self.search.regex {
+            for (line_idx, line) in self.lines.iter().enumerate() {
+                let line: String = line.iter().map(|c| c.0).collect();
+                for m in regex.find_iter(&line).filter(|m| !m.is_empty()) {
+                    let column = byte_to_column(&line, m.start());
+                    self.search.matches.push(SearchMatch {
+                        line: line_idx,
+                        column,
+                        len: m.as_str().chars().count(),
+                    });
+                }
+            }
+        }
+
+        if self
+            .search
+            .current
+            .is_some_and(|current| current >= self.search.matches.len())
+        {
+            self.search.current = None;
+        }
+    }
+
+    /// Moves the cursor to the next match after the cursor, wrapping around at the end
+    pub fn find_next(&mut self) -> bool {
+        let cursor = self.cursor_pos();
+        let next = self
+            .search
+            .matches
+            .iter()
+            .position(|m| (m.line, m.column) > cursor)
+            .or((!self.search.matches.is_empty()).then_some(0));
+        self.jump_to_match(next)
+    }
+
+    /// Moves the cursor to the previous match before the cursor, wrapping around at the start
+    pub fn find_previous(&mut self) -> bool {
+        let cursor = self.cursor_pos();
+        let previous = self
+            .search
+            .matches
+            .iter()
+            .rposition(|m| (m.line, m.column) < cursor)
+            .or(self.search.matches.len().checked_sub(1));
+        self.jump_to_match(previous)
+    }
+
+    fn jump_to_match(&mut self, idx: Option<usize>) -> bool {
+        let Some(m) = idx.and_then(|idx| self.search.matches.get(idx).copied()) else {
+            return false;
+        };
+        self.search.current = idx;
+        self.selection_anchor = None;
+        self.set_cursor(m.line, m.column);
+        true
+    }
+
+    // text that replaces `m`. Regex patterns expand `$1`-style capture references.
+    fn expand_replacement(&self, m: SearchMatch, replacement: &str) -> Option<String> {
+        if !self.search.use_regex {
+            return Some(replacement.to_string());
+        }
+        let regex = self.search.regex.as_ref()?;
+        let line: String = self.lines[m.line].iter().map(|c| c.0).collect();
+        let start = line.char_indices().nth(m.column).map(|(idx, _)| idx)?;
+        // `captures_at` finds the first match at or after `start`, which has to be `m` itself
+        let captures = regex
+            .captures_at(&line, start)
+            .filter(|captures| captures.get(0).is_some_and(|m| m.start() == start))?;
+        let mut expanded = String::new();
+        captures.expand(replacement, &mut expanded);
+        Some(expanded)
+    }
+
+    /// Replaces the current match (or the next one if there is none) and jumps to the next match
+    pub fn replace_current(&mut self, replacement: &str) -> bool {
+        if self.search.current.is_none() && !self.find_next() {
+            return false;
+        }
+        let Some(m) = self
+            .search
+            .current
+            .and_then(|idx| self.search.matches.get(idx).copied())
+        else {
+            return false;
+        };
+        let Some(replacement) = self.expand_replacement(m, replacement) else {
+            return false;
+        };
+
+        self.selection_anchor = None;
+        self.begin_edit(EditKind::Other, m.line, 1);
+        // the replacement can contain newlines, so the line is rebuilt and split again
+        let line = &self.lines[m.line];
+        let mut text: String = line[..m.column].iter().map(|c| c.0).collect();
+        text.push_str(&replacement);
+        let (end_line, end_column) = match replacement.rsplit_once('\n') {
+            Some((before, after)) => (
+                m.line + before.matches('\n').count() + 1,
+                after.chars().count(),
+            ),
+            None => (m.line, m.column + replacement.chars().count()),
+        };
+        text.extend(line[m.column + m.len..].iter().map(|c| c.0));
+        let new_lines: Vec<Vec<Cell>> = text
+            .split('\n')
+            .map(|line| line.chars().map(Cell::from).collect())
+            .collect();
+        self.lines.splice(m.line..=m.line, new_lines);
+
+        self.set_cursor(end_line, end_column);
+        self.end_edit(false);
+        for line in m.line..=end_line {
+            self.highlight_line(line);
+        }
+
+        self.search.current = None;
+        self.find_next();
+        true
+    }
+
+    /// Replaces every match as a single undo step. Returns the amount of replaced matches.
+    pub fn replace_all(&mut self, replacement: &str) -> usize {
+        let Some(regex) = self.search.regex.clone() else {
+            return 0;
+        };
+        if self.search.matches.is_empty() {
+            return 0;
+        }
+
+        self.selection_anchor = None;
+        self.begin_edit(EditKind::Other, 0, self.lines.len());
+        let mut count = 0;
+        let mut new_lines = Vec::with_capacity(self.lines.len());
+        for line in &self.lines {
+            let text: String = line.iter().map(|c| c.0).collect();
+            let replaced = replace_matches(&regex, &text, replacement, self.search.use_regex);
+            let Some((replaced, replaced_count)) = replaced else {
+                new_lines.push(line.clone());
+                continue;
+            };
+            count += replaced_count;
+            // replacements with newlines split the line
+            new_lines.extend(
+                replaced
+                    .split('\n')
+                    .map(|line| line.chars().map(Cell::from).collect()),
+            );
+        }
+        self.lines = new_lines;
+        let (line, column) = self.cursor_pos();
+        self.set_cursor(line, column);
+        self.end_edit(false);
+        self.highlight_all();
+
+        count
+    }
+
+    /// Returns the position of the current match and the amount of matches, formatted as " 3/12"
+    pub fn search_status(&self) -> String {
+        if self.search.pattern.is_empty() {
+            String::new()
+        } else if self.search.regex.is_none() {
+            " invalid regex".to_string()
+        } else if let Some(current) = self.search.current {
+            format!(" {}/{}", current + 1, self.search.matches.len())
+        } else {
+            format!(" -/{}", self.search.matches.len())
+        }
+    }
+}
+
+// history
+impl TextBuffer {
+    fn cursor_state(&self) -> CursorState {
+        CursorState {
+            cursor_x: self.cursor_x,
+            cursor_y: self.cursor_y,
+            offset_y: self.offset_y,
+        }
+    }
+
+    fn begin_edit(&mut self, kind: EditKind, start: usize, line_count: usize) {
+        let cursor = self.cursor_state();
+        self.begin_edit_with_cursor(kind, start, line_count, cursor);
+    }
+
+    fn begin_edit_with_cursor(
+        &mut self,
+        kind: EditKind,
+        start: usize,
+        line_count: usize,
+        cursor: CursorState,
+    ) {
+        let start = start.min(self.lines.len());
+        let end = (start + line_count).min(self.lines.len());
+        let lines = self.lines[start..end]
+            .iter()
+            .map(|line| line.iter().map(|c| c.0).collect())
+            .collect();
+        self.history
+            .begin(kind, start, lines, self.lines.len(), cursor);
+    }
+
+    fn end_edit(&mut self, coalesce: bool) {
+        let cursor = self.cursor_state();
+        self.history.end(self.lines.len(), cursor, coalesce);
+        self.version += 1;
+        self.refresh_search();
+    }
+
+    /// Changes whenever the text changes, used to notice edits without comparing the text
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Reverts the last undo step. Returns false if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(entry) = self.history.pop_undo() else {
+            return false;
+        };
+        let inverse = self.apply_history_entry(entry);
+        self.history.push_redo(inverse);
+        true
+    }
+
+    /// Reapplies the last undone step. Returns false if there was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(entry) = self.history.pop_redo() else {
+            return false;
+        };
+        let inverse = self.apply_history_entry(entry);
+        self.history.push_undo(inverse);
+        true
+    }
+
+    fn apply_history_entry(&mut self, entry: HistoryEntry) -> HistoryEntry {
+        let start = entry.start.min(self.lines.len());
+        let end = (start + entry.len).min(self.lines.len());
+        let inserted = entry.lines.len();
+        let removed = self
+            .lines
+            .splice(
+                start..end,
+                entry
+                    .lines
+                    .into_iter()
+                    .map(|line| line.into_iter().map(Cell::from).collect()),
+            )
+            .map(|line| line.into_iter().map(|c| c.0).collect())
+            .collect();
+        if self.lines.is_empty() {
+            self.lines.push(vec![]);
+        }
+
+        let inverse = HistoryEntry {
+            start,
+            len: inserted,
+            lines: removed,
+            cursor: self.cursor_state(),
+        };
+
+        self.cursor_x = entry.cursor.cursor_x;
+        self.cursor_y = entry.cursor.cursor_y;
+        self.offset_y = entry.cursor.offset_y;
+        self.selection_anchor = None;
+
+        for line in start..(start + inserted).min(self.lines.len()) {
+            self.highlight_line(line);
+        }
+        self.version += 1;
+        self.refresh_search();
+
+        inverse
+    }
+}
+
 // drawing
 impl TextBuffer {
     pub fn to_string(&self) -> String {
@@ -332,6 +938,14 @@ impl TextBuffer {
         string
     }
 
+    pub fn size(&self) -> Size {
+        (self.width, self.height).into()
+    }
+
+    pub fn set_error_line(&mut self, line: Option<usize>) {
+        self.error_line = line;
+    }
+
     pub fn resize(&mut self, new_width: usize, new_height: usize) {
         self.width = new_width;
         self.height = new_height;
@@ -358,7 +972,14 @@ impl TextBuffer {
             let line_num = format!("{}{} ", " ".repeat(4 - line_num.len()), line_num);
 
             let mut line_num_style = Style::new();
-            line_num_style.set_fg((88, 88, 88).into());
+            if self.error_line == Some(self.offset_y + y) {
+                line_num_style.set_fg(Color::Red);
+                line_num_style.set_bold(true);
+                canvas.put('!', line_num_style, (0, y as u16));
+            } else {
+                line_num_style.set_fg((88, 88, 88).into());
+                canvas.erase((0, y as u16));
+            }
 
             for x in 1..5 {
                 canvas.put(
@@ -368,11 +989,44 @@ impl TextBuffer {
                 );
             }
 
+            let line_idx = y + self.offset_y;
+            let selected = self.selected_columns(line_idx);
+            let line_matches: Vec<(usize, &SearchMatch)> = self
+                .search
+                .matches
+                .iter()
+                .enumerate()
+                .filter(|(_, m)| m.line == line_idx)
+                .collect();
+            let background = |column: usize| -> Option<Color> {
+                if matches!(selected, Some((from, to)) if column >= from && column < to) {
+                    return Some((60, 70, 110).into());
+                }
+                let (idx, _) = line_matches
+                    .iter()
+                    .find(|(_, m)| column >= m.column && column < m.column + m.len)?;
+                if Some(*idx) == self.search.current {
+                    Some((200, 140, 40).into())
+                } else {
+                    Some((100, 80, 30).into())
+                }
+            };
+
             // probably safe to assume we wont have to show line numbers with more than 3 digits
             for x in 5..size.width {
-                match line.next() {
-                    Some(c) => canvas.put(c.0, c.1.to_style(), (x as u16, y as u16)),
-                    None => canvas.erase((x as u16, y as u16)),
+                match (line.next(), background(x - 5)) {
+                    (Some(c), Some(bg)) => {
+                        let mut style = c.1.to_style();
+                        style.set_bg(bg);
+                        canvas.put(c.0, style, (x as u16, y as u16));
+                    }
+                    (Some(c), None) => canvas.put(c.0, c.1.to_style(), (x as u16, y as u16)),
+                    (None, Some(bg)) => {
+                        let mut style = Style::new();
+                        style.set_bg(bg);
+                        canvas.put(' ', style, (x as u16, y as u16));
+                    }
+                    (None, None) => canvas.erase((x as u16, y as u16)),
                 }
             }
         }
@@ -562,3 +1216,95 @@ impl TextBuffer {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn buffer(text: &str) -> TextBuffer {
+        TextBuffer::from_iter(text.chars(), 80, 24)
+    }
+
+    fn search(buffer: &mut TextBuffer, pattern: &str, use_regex: bool) {
+        buffer.set_search_regex(use_regex);
+        buffer.set_search(pattern);
+    }
+
+    #[test]
+    fn replacement_expands_capture_groups() {
+        let mut buffer = buffer("set width=10");
+        search(&mut buffer, r"(\w+)=(\d+)", true);
+        assert!(buffer.replace_current("$2=$1"));
+        assert_eq!(buffer.to_string(), "set 10=width");
+    }
+
+    #[test]
+    fn replace_all_expands_capture_groups() {
+        let mut buffer = buffer("width=10\nheight=20");
+        search(&mut buffer, r"(\w+)=(\d+)", true);
+        assert_eq!(buffer.replace_all("[$1: $2]"), 2);
+        assert_eq!(buffer.to_string(), "[width: 10]\n[height: 20]");
+    }
+
+    #[test]
+    fn literal_replacement_keeps_dollar_signs() {
+        let mut buffer = buffer("a b");
+        search(&mut buffer, "a", false);
+        assert_eq!(buffer.replace_all("$1"), 1);
+        assert_eq!(buffer.to_string(), "$1 b");
+    }
+
+    #[test]
+    fn multi_line_replacement_splits_the_line() {
+        let mut buffer = buffer("vstack,text");
+        search(&mut buffer, ",", false);
+        assert!(buffer.replace_current("\n    "));
+        assert_eq!(buffer.to_string(), "vstack\n    text");
+        assert_eq!(buffer.cursor_pos(), (1, 4));
+    }
+
+    #[test]
+    fn replace_all_with_newlines() {
+        let mut buffer = buffer("a,b\nc");
+        search(&mut buffer, ",", false);
+        assert_eq!(buffer.replace_all("\n"), 1);
+        assert_eq!(buffer.to_string(), "a\nb\nc");
+    }
+
+    #[test]
+    fn replace_all_does_not_overlap_matches() {
+        let mut buffer = buffer("aaaaa");
+        search(&mut buffer, "aa", false);
+        assert_eq!(buffer.replace_all("b"), 2);
+        assert_eq!(buffer.to_string(), "bba");
+    }
+
+    #[test]
+    fn replace_all_skips_empty_matches() {
+        let mut buffer = buffer("axxb\ncd");
+        search(&mut buffer, "x*", true);
+        assert_eq!(buffer.replace_all("-"), 1);
+        assert_eq!(buffer.to_string(), "a-b\ncd");
+    }
+
+    #[test]
+    fn replace_all_with_only_empty_matches_changes_nothing() {
+        let mut buffer = buffer("abc");
+        search(&mut buffer, "x*", true);
+        assert_eq!(buffer.replace_all("-"), 0);
+        assert_eq!(buffer.to_string(), "abc");
+        assert!(!buffer.undo());
+    }
+
+    #[test]
+    fn replace_all_is_undone_in_one_step() {
+        let mut buffer = buffer("foo bar\nfoo\nbaz foo");
+        search(&mut buffer, "foo", false);
+        assert_eq!(buffer.replace_all("x\ny"), 3);
+        assert!(buffer.undo());
+        assert_eq!(buffer.to_string(), "foo bar\nfoo\nbaz foo");
+        assert!(!buffer.undo());
+        assert!(buffer.redo());
+        assert_eq!(buffer.to_string(), "x\ny bar\nx\ny\nbaz x\ny");
+    }
+}
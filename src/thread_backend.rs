@@ -1,15 +1,35 @@
-use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::panic::AssertUnwindSafe;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::Once;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use anathema::backend::tui::{Buffer, Style};
 use anathema::backend::Backend;
+use anathema::default_widgets::Canvas;
 use anathema::geometry::{LocalPos, Pos, Size};
-use anathema::prelude::Document;
-use anathema::runtime::Runtime;
-use anathema::widgets::components::events::Event;
+use anathema::prelude::{Document, ToSourceKind};
+use anathema::runtime::{Error as RuntimeError, Runtime};
+use anathema::templates::error::Error as TemplateError;
+use anathema::widgets::components::events::{Event, KeyEvent, MouseEvent};
 use anathema::widgets::paint::{CellAttributes, Glyph};
 use anathema::widgets::{GlyphMap, WidgetRenderer};
 
+use crate::preview_state::{PreviewRoot, PreviewState, ROOT_COMPONENT};
+use crate::protocol::{self, Launch};
+
+/// The hidden command line flag that starts the playground as a preview worker.
+pub const WORKER_FLAG: &str = "--preview-worker";
+
+// how often the preview reports that its event loop is still running
+const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(250);
+// how long a closed worker process gets to stop before it is killed
+const CLOSE_TIMEOUT: Duration = Duration::from_millis(500);
+
 struct BufferRender<'a>(&'a mut Buffer);
 
 impl WidgetRenderer for BufferRender<'_> {
@@ -36,11 +56,130 @@ impl WidgetRenderer for BufferRender<'_> {
 pub enum ThreadEvent {
     Quit,
     Resize { width: u16, height: u16 },
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+}
+
+pub enum ThreadMessage {
+    /// The document compiled and the runtime is starting
+    Ready,
+    /// The event loop is still running, sent every 250ms whether or not there are frames
+    Heartbeat,
+    Frame(FrameDiff),
+    CompileError(CompileError),
+}
+
+#[derive(Debug, Clone)]
+pub struct CompileError {
+    pub message: String,
+    // 1-based, if anathema knows where the error is
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    // the component the error is in, None if it is in the document itself
+    pub component: Option<String>,
+}
+
+impl CompileError {
+    /// An error without a position, e.g. a crashed worker
+    pub(crate) fn new(message: String) -> Self {
+        Self {
+            message,
+            line: None,
+            column: None,
+            component: None,
+        }
+    }
+
+    /// Only parse errors know where they happened. Other template errors (e.g. a missing component
+    /// or a circular dependency) are reported without a position.
+    pub(crate) fn from_template(error: &TemplateError) -> Self {
+        match error {
+            TemplateError::ParseError(parse_error) => Self {
+                message: error.to_string(),
+                line: Some(parse_error.line),
+                column: Some(parse_error.col),
+                component: None,
+            },
+            _ => Self::new(error.to_string()),
+        }
+    }
+
+    pub(crate) fn from_runtime(error: &RuntimeError) -> Self {
+        match error {
+            RuntimeError::Template(error) => Self::from_template(error),
+            _ => Self::new(error.to_string()),
+        }
+    }
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "{line}:{column}: {}", self.message),
+            (Some(line), None) => write!(f, "{line}: {}", self.message),
+            _ => f.write_str(&self.message),
+        }
+    }
+}
+
+/// What a cell of a frame shows.
+#[derive(Clone, PartialEq)]
+pub enum Grapheme {
+    /// Nothing was drawn here
+    Empty,
+    /// A grapheme that is a single char, which most are
+    Char(char),
+    /// A grapheme made of several chars, e.g. a letter with combining marks or an emoji sequence
+    Cluster(Box<str>),
+    /// Covered by the wide grapheme to the left
+    Continuation,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Cell {
+    pub grapheme: Grapheme,
+    /// The number of columns the grapheme spans, the cells it covers after the first one are
+    /// continuations
+    pub width: u8,
+    pub style: Style,
+}
+
+impl Cell {
+    pub fn empty() -> Self {
+        Self {
+            grapheme: Grapheme::Empty,
+            width: 0,
+            style: Style::reset(),
+        }
+    }
+
+    pub fn char(c: char, style: Style) -> Self {
+        Self {
+            grapheme: Grapheme::Char(c),
+            width: 1,
+            style,
+        }
+    }
+
+    /// Paints the cell at `pos`. Continuations are left empty, so that the right half of a wide
+    /// grapheme isn't painted over.
+    pub fn paint(&self, canvas: &mut Canvas, pos: (u16, u16)) {
+        match &self.grapheme {
+            Grapheme::Char(c) => canvas.put(*c, self.style, pos),
+            // `Canvas::put` takes a single char, so only the first char of a cluster can be shown
+            // and combining marks or the rest of an emoji sequence are lost. Exports keep them.
+            Grapheme::Cluster(cluster) => match cluster.chars().next() {
+                Some(c) => canvas.put(c, self.style, pos),
+                None => canvas.erase(pos),
+            },
+            Grapheme::Empty | Grapheme::Continuation => canvas.erase(pos),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct RenderedBuffer {
-    value: Box<[(char, Style)]>,
+    value: Box<[Cell]>,
     width: usize,
     height: usize,
 }
@@ -50,27 +189,179 @@ impl RenderedBuffer {
         (self.width, self.height).into()
     }
 
-    pub fn get(&self, x: usize, y: usize) -> &(char, Style) {
+    pub fn get(&self, x: usize, y: usize) -> &Cell {
         &self.value[x + y * self.width]
     }
 
-    pub(crate) fn set_at(&mut self, x: usize, y: usize, character: char, style: Style) {
-        self.value[x + y * self.width] = (character, style);
+    pub(crate) fn set_at(&mut self, x: usize, y: usize, cell: Cell) {
+        self.value[x + y * self.width] = cell;
     }
 
     pub(crate) fn create(width: usize, height: usize) -> Self {
         Self {
             width,
             height,
-            value: vec![('\0', Style::reset()); width * height].into_boxed_slice(),
+            value: vec![Cell::empty(); width * height].into_boxed_slice(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.value.fill(Cell::empty());
+    }
+
+    /// A frame that shows `text`, with `prefix` in front of its first line.
+    pub(crate) fn message(prefix: &str, text: &str) -> Self {
+        let mut width = 0;
+        let mut height = 3;
+        let mut last_width = prefix.len();
+        for char in text.chars() {
+            if char == '\n' {
+                width = width.max(last_width);
+                last_width = 0;
+                height += 1;
+            } else {
+                last_width += 1;
+            }
+        }
+        width = width.max(last_width);
+
+        let mut buffer = RenderedBuffer::create(width, height);
+
+        for (x, c) in prefix.chars().enumerate() {
+            buffer.set_at(x, 1, Cell::char(c, Style::reset()));
+        }
+
+        for (y, line) in text.lines().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                if c == '\n' {
+                    break;
+                }
+                if y == 0 {
+                    buffer.set_at(x + prefix.len(), y + 1, Cell::char(c, Style::reset()));
+                } else {
+                    buffer.set_at(x, y + 1, Cell::char(c, Style::reset()));
+                }
+            }
+        }
+
+        buffer
+    }
+}
+
+/// A run of cells that changed, starting at `x`, `y` and continuing to the right on the same row.
+#[derive(Clone)]
+pub struct Run {
+    pub x: usize,
+    pub y: usize,
+    pub cells: Vec<Cell>,
+}
+
+/// The difference between two frames as runs of changed cells. If the size changed, the runs cover
+/// the whole frame.
+#[derive(Clone)]
+pub struct FrameDiff {
+    width: usize,
+    height: usize,
+    full: bool,
+    runs: Vec<Run>,
+}
+
+impl FrameDiff {
+    pub(crate) fn new(width: usize, height: usize, full: bool, runs: Vec<Run>) -> Self {
+        Self {
+            width,
+            height,
+            full,
+            runs,
+        }
+    }
+
+    /// Every cell of `buffer`, one run per row.
+    pub fn full(buffer: &RenderedBuffer) -> Self {
+        let runs = (0..buffer.height)
+            .map(|y| Run {
+                x: 0,
+                y,
+                cells: buffer.value[y * buffer.width..(y + 1) * buffer.width].to_vec(),
+            })
+            .collect();
+        Self::new(buffer.width, buffer.height, true, runs)
+    }
+
+    pub fn between(previous: &RenderedBuffer, current: &RenderedBuffer) -> Self {
+        if previous.size() != current.size() {
+            return Self::full(current);
+        }
+
+        let mut runs = Vec::new();
+        for y in 0..current.height {
+            let mut x = 0;
+            while x < current.width {
+                if previous.get(x, y) == current.get(x, y) {
+                    x += 1;
+                    continue;
+                }
+                let start = x;
+                while x < current.width && previous.get(x, y) != current.get(x, y) {
+                    x += 1;
+                }
+                runs.push(Run {
+                    x: start,
+                    y,
+                    cells: (start..x).map(|x| current.get(x, y).clone()).collect(),
+                });
+            }
+        }
+        Self::new(current.width, current.height, false, runs)
+    }
+
+    pub fn size(&self) -> Size {
+        (self.width, self.height).into()
+    }
+
+    /// True if the diff replaces the whole frame rather than updating the previous one.
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.full && self.runs.is_empty()
+    }
+
+    pub fn runs(&self) -> &[Run] {
+        &self.runs
+    }
+
+    /// Updates `buffer`, which has to hold the frame this diff was made against.
+    pub fn apply(&self, buffer: &mut RenderedBuffer) {
+        if self.full || buffer.size() != self.size() {
+            *buffer = RenderedBuffer::create(self.width, self.height);
+        }
+        for run in &self.runs {
+            if run.y >= self.height {
+                continue;
+            }
+            for (offset, cell) in run.cells.iter().enumerate() {
+                let x = run.x + offset;
+                if x >= self.width {
+                    break;
+                }
+                buffer.set_at(x, run.y, cell.clone());
+            }
         }
     }
 }
 
 pub struct ThreadBackend {
-    buffer_sender: Sender<RenderedBuffer>,
+    buffer_sender: Sender<ThreadMessage>,
     event_receiver: Receiver<ThreadEvent>,
     buffer: Buffer,
+    last_heartbeat: Instant,
+    // the editor stopped listening, the run loop is stopped by the next event
+    disconnected: bool,
+    // the last frame that was sent and the one being rendered, swapped after every frame
+    previous: RenderedBuffer,
+    current: RenderedBuffer,
 }
 
 impl Backend for ThreadBackend {
@@ -79,11 +370,23 @@ impl Backend for ThreadBackend {
     }
 
     fn next_event(&mut self, _: std::time::Duration) -> Option<Event> {
+        if self.last_heartbeat.elapsed() >= HEARTBEAT_INTERVAL {
+            self.last_heartbeat = Instant::now();
+            if self.buffer_sender.send(ThreadMessage::Heartbeat).is_err() {
+                self.disconnected = true;
+            }
+        }
+        if self.disconnected {
+            return Some(Event::Stop);
+        }
+
         match self.event_receiver.try_recv() {
             Err(TryRecvError::Empty) => None,
             Err(_) => Some(Event::Stop), // if the connection is closed, close the thread
             Ok(ThreadEvent::Quit) => Some(Event::Stop),
             Ok(ThreadEvent::Resize { width, height }) => Some(Event::Resize(width, height)),
+            Ok(ThreadEvent::Key(key)) => Some(Event::Key(key)),
+            Ok(ThreadEvent::Mouse(mouse)) => Some(Event::Mouse(mouse)),
         }
     }
 
@@ -113,28 +416,73 @@ impl Backend for ThreadBackend {
 
     fn render(&mut self, glyph_map: &mut GlyphMap) {
         let size = self.buffer.size();
-        let mut rendered_buffer = RenderedBuffer::create(size.width, size.height);
-
-        for x in 0..size.width {
-            for y in 0..size.height {
-                if let Some((&glyph, &style)) = self.buffer.get((x as u16, y as u16).into()) {
-                    match glyph {
-                        Glyph::Single(c, _) => rendered_buffer.set_at(x, y, c, style),
-                        Glyph::Cluster(idx, _) => {
-                            if let Some(value) = glyph_map.get(idx) {
-                                for (offset_x, character) in value.chars().enumerate() {
-                                    rendered_buffer.set_at(x + offset_x, y, character, style);
-                                }
-                            }
+        if self.current.size() == size {
+            self.current.clear();
+        } else {
+            self.current = RenderedBuffer::create(size.width, size.height);
+        }
+        let rendered_buffer = &mut self.current;
+
+        for y in 0..size.height {
+            let mut x = 0;
+            while x < size.width {
+                let Some((&glyph, &style)) = self.buffer.get((x as u16, y as u16).into()) else {
+                    x += 1;
+                    continue;
+                };
+                let (grapheme, width) = match glyph {
+                    Glyph::Single(c, width) => (Grapheme::Char(c), width),
+                    Glyph::Cluster(idx, width) => match glyph_map.get(idx) {
+                        Some(value) => (Grapheme::Cluster(value[..].into()), width),
+                        None => {
+                            x += 1;
+                            continue;
                         }
-                    }
+                    },
+                };
+                // zero width graphemes still take up the cell they were drawn to
+                let width = width.max(1);
+                let columns = usize::from(width);
+                // a wide grapheme that doesn't fit on the row is left out
+                if x + columns > size.width {
+                    break;
                 }
+
+                rendered_buffer.set_at(
+                    x,
+                    y,
+                    Cell {
+                        grapheme,
+                        width,
+                        style,
+                    },
+                );
+                for continuation in x + 1..x + columns {
+                    rendered_buffer.set_at(
+                        continuation,
+                        y,
+                        Cell {
+                            grapheme: Grapheme::Continuation,
+                            width: 0,
+                            style,
+                        },
+                    );
+                }
+                x += columns;
             }
         }
 
-        match self.buffer_sender.send(rendered_buffer) {
-            Err(_) => panic!("failed to send updates"),
-            Ok(_) => (),
+        // only the cells that changed since the last frame are sent
+        let diff = FrameDiff::between(&self.previous, &self.current);
+        std::mem::swap(&mut self.previous, &mut self.current);
+        if diff.is_empty() {
+            return;
+        }
+
+        // the editor closes the preview without waiting for it, so this happens on a normal
+        // shutdown as well
+        if self.buffer_sender.send(ThreadMessage::Frame(diff)).is_err() {
+            self.disconnected = true;
         }
     }
 
@@ -150,16 +498,99 @@ impl Backend for ThreadBackend {
     }
 }
 
+/// Where the preview runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreviewMode {
+    /// On a thread of the editor's process
+    Thread,
+    /// In a child process, so that an abort or a stack overflow in the preview can't take the
+    /// editor down with it
+    Process,
+}
+
+enum Worker {
+    // the thread is detached, it can't be stopped from the outside anyway
+    Thread,
+    Process {
+        child: Child,
+        // collects the worker's stderr, taken once the crash was reported
+        stderr: Option<JoinHandle<String>>,
+    },
+}
+
 pub struct AnathemaThreadHandle {
-    thread_handle: JoinHandle<()>,
-    buffer_receiver: Receiver<RenderedBuffer>,
+    worker: Worker,
+    buffer_receiver: Receiver<ThreadMessage>,
     event_sender: Sender<ThreadEvent>,
+    // a frame that arrived while waiting for the document to compile
+    pending_frame: Option<FrameDiff>,
+    // when the preview was started, see `poll_ready`
+    launched: Instant,
+    // when the preview last sent anything, see `is_hung`
+    last_seen: Instant,
 }
 
 impl AnathemaThreadHandle {
+    /// Asks the preview to stop without waiting for it. A worker process that doesn't stop in time
+    /// is killed by a thread of its own, so a preview stuck in a loop can't block the editor.
     pub fn close(self) {
         _ = self.event_sender.send(ThreadEvent::Quit);
-        _ = self.thread_handle.join();
+        match self.worker {
+            // a thread can't be stopped from the outside, it finishes on its own after the quit
+            // event or keeps running in the background
+            Worker::Thread => {}
+            Worker::Process { mut child, .. } => {
+                std::thread::spawn(move || {
+                    let start = Instant::now();
+                    while start.elapsed() < CLOSE_TIMEOUT {
+                        if !matches!(child.try_wait(), Ok(None)) {
+                            return;
+                        }
+                        std::thread::sleep(Duration::from_millis(10));
+                    }
+                    _ = child.kill();
+                    _ = child.wait();
+                });
+            }
+        }
+    }
+
+    /// Gives up on the preview without waiting for it. A worker process is killed, a thread can't
+    /// be stopped from the outside and keeps running in the background.
+    pub fn abandon(self) {
+        match self.worker {
+            Worker::Thread => {}
+            Worker::Process { mut child, .. } => {
+                _ = child.kill();
+                _ = child.wait();
+            }
+        }
+    }
+
+    /// True if the preview has not sent a frame or a heartbeat for `timeout`, e.g. because the
+    /// layout of the template never finishes.
+    pub fn is_hung(&self, timeout: Duration) -> bool {
+        self.last_seen.elapsed() >= timeout
+    }
+
+    /// Describes how the worker process died, if it did not exit cleanly. Only reported once.
+    fn crash_report(&mut self) -> Option<String> {
+        let Worker::Process { child, stderr } = &mut self.worker else {
+            return None;
+        };
+        let stderr = stderr.take()?;
+        let status = child.wait().ok()?;
+        if status.success() {
+            return None;
+        }
+
+        let stderr = stderr.join().unwrap_or_default();
+        let stderr = stderr.trim();
+        if stderr.is_empty() {
+            Some(format!("the preview process exited ({status})"))
+        } else {
+            Some(format!("the preview process exited ({status})\n{stderr}"))
+        }
     }
 
     pub fn resize(&mut self, new_width: u16, new_height: u16) -> Result<(), ()> {
@@ -171,25 +602,239 @@ impl AnathemaThreadHandle {
             .map_err(|_| ())
     }
 
-    pub fn get_buffer(&self) -> Result<Option<RenderedBuffer>, ()> {
-        match self.buffer_receiver.try_recv() {
-            Ok(v) => Ok(Some(v)),
-            Err(TryRecvError::Empty) => Ok(None),
-            Err(_) => Err(()),
+    pub fn send_key(&mut self, key: KeyEvent) -> Result<(), ()> {
+        self.event_sender
+            .send(ThreadEvent::Key(key))
+            .map_err(|_| ())
+    }
+
+    pub fn send_mouse(&mut self, mouse: MouseEvent) -> Result<(), ()> {
+        self.event_sender
+            .send(ThreadEvent::Mouse(mouse))
+            .map_err(|_| ())
+    }
+
+    /// Returns the changes since the last frame. A crashed worker process is reported as a frame
+    /// that describes the crash.
+    pub fn get_buffer(&mut self) -> Result<Option<FrameDiff>, ()> {
+        if let Some(frame) = self.pending_frame.take() {
+            return Ok(Some(frame));
+        }
+
+        loop {
+            let message = self.buffer_receiver.try_recv();
+            if message.is_ok() {
+                self.last_seen = Instant::now();
+            }
+            match message {
+                Ok(ThreadMessage::Frame(v)) => return Ok(Some(v)),
+                Ok(
+                    ThreadMessage::Ready
+                    | ThreadMessage::Heartbeat
+                    | ThreadMessage::CompileError(_),
+                ) => continue,
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(_) => {
+                    return match self.crash_report() {
+                        Some(report) => Ok(Some(FrameDiff::full(&RenderedBuffer::message(
+                            "Crashed: ",
+                            &report,
+                        )))),
+                        None => Err(()),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Waits until the document compiled. If compiling takes longer than `timeout`, the preview
+    /// is assumed to be running.
+    pub fn wait_until_ready(&mut self, timeout: Duration) -> Result<(), CompileError> {
+        let message = self.buffer_receiver.recv_timeout(timeout);
+        self.ready(message)
+    }
+
+    /// Like [`wait_until_ready`](Self::wait_until_ready) without blocking, `timeout` counts from
+    /// the launch. Returns `None` while the document is still compiling.
+    pub fn poll_ready(&mut self, timeout: Duration) -> Option<Result<(), CompileError>> {
+        let message = match self.buffer_receiver.try_recv() {
+            Ok(message) => Ok(message),
+            Err(TryRecvError::Empty) if self.launched.elapsed() < timeout => return None,
+            Err(TryRecvError::Empty) => Err(RecvTimeoutError::Timeout),
+            Err(TryRecvError::Disconnected) => Err(RecvTimeoutError::Disconnected),
+        };
+        Some(self.ready(message))
+    }
+
+    // interprets the first message of the preview
+    fn ready(
+        &mut self,
+        message: Result<ThreadMessage, RecvTimeoutError>,
+    ) -> Result<(), CompileError> {
+        if message.is_ok() {
+            self.last_seen = Instant::now();
+        }
+        match message {
+            Ok(ThreadMessage::Ready | ThreadMessage::Heartbeat)
+            | Err(RecvTimeoutError::Timeout) => Ok(()),
+            Ok(ThreadMessage::CompileError(error)) => Err(error),
+            // a frame before the runtime is ready is the panic hook reporting a panic
+            Ok(ThreadMessage::Frame(frame)) => {
+                self.pending_frame = Some(frame);
+                Ok(())
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(CompileError::new(
+                self.crash_report()
+                    .unwrap_or_else(|| "the preview stopped before it was ready".to_string()),
+            )),
         }
     }
 }
 
-pub fn launch_threaded_anathema(
+/// Starts the preview of `document`, see [`PreviewMode`]. `components` are (name, template) pairs
+/// that get registered before the document is compiled, `state` is the document's root state.
+pub fn launch_anathema(
+    mode: PreviewMode,
     document: String,
+    components: Vec<(String, String)>,
+    state: Option<PreviewState>,
+    initial_size: Size,
+) -> Result<AnathemaThreadHandle, std::io::Error> {
+    match mode {
+        PreviewMode::Thread => launch_threaded_anathema(document, components, state, initial_size),
+        PreviewMode::Process => launch_process_anathema(document, components, state, initial_size),
+    }
+}
+
+/// Runs `document` on a new thread.
+fn launch_threaded_anathema(
+    document: String,
+    components: Vec<(String, String)>,
+    state: Option<PreviewState>,
     initial_size: Size,
 ) -> Result<AnathemaThreadHandle, std::io::Error> {
     let (buffer_sender, buffer_receiver) = channel();
     let (event_sender, event_receiver) = channel();
 
-    let thread_handle = std::thread::Builder::new().spawn(move || {
-        let panic_sender = buffer_sender.clone();
+    std::thread::Builder::new().spawn(move || {
+        install_panic_hook(buffer_sender.clone());
+        // a panic has already been sent to the editor by the hook
+        _ = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            run_anathema(
+                document,
+                components,
+                state,
+                initial_size,
+                buffer_sender,
+                event_receiver,
+            )
+        }));
+        remove_panic_hook();
+    })?;
+
+    Ok(AnathemaThreadHandle {
+        worker: Worker::Thread,
+        buffer_receiver,
+        event_sender,
+        pending_frame: None,
+        launched: Instant::now(),
+        last_seen: Instant::now(),
+    })
+}
+
+/// Runs `document` in a copy of the playground started with [`WORKER_FLAG`]. The events and
+/// frames are passed through its stdin and stdout by two threads, see [`protocol`].
+fn launch_process_anathema(
+    document: String,
+    components: Vec<(String, String)>,
+    state: Option<PreviewState>,
+    initial_size: Size,
+) -> Result<AnathemaThreadHandle, std::io::Error> {
+    let mut child = Command::new(std::env::current_exe()?)
+        .arg(WORKER_FLAG)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let (Some(stdin), Some(stdout), Some(mut stderr)) =
+        (child.stdin.take(), child.stdout.take(), child.stderr.take())
+    else {
+        _ = child.kill();
+        return Err(std::io::Error::other(
+            "failed to open the pipes to the preview",
+        ));
+    };
+
+    let mut stdin = BufWriter::new(stdin);
+    let launch = Launch {
+        document,
+        components,
+        state,
+        size: initial_size,
+    };
+    if let Err(error) = protocol::write_launch(&mut stdin, &launch) {
+        _ = child.kill();
+        _ = child.wait();
+        return Err(error);
+    }
+
+    let (buffer_sender, buffer_receiver) = channel();
+    let (event_sender, event_receiver) = channel::<ThreadEvent>();
+
+    std::thread::Builder::new().spawn(move || {
+        for event in event_receiver {
+            if protocol::write_event(&mut stdin, &event).is_err() {
+                break;
+            }
+            if stdin.flush().is_err() {
+                break;
+            }
+        }
+    })?;
+    std::thread::Builder::new().spawn(move || {
+        let mut stdout = BufReader::new(stdout);
+        while let Ok(Some(message)) = protocol::read_message(&mut stdout) {
+            if buffer_sender.send(message).is_err() {
+                break;
+            }
+        }
+    })?;
+    let stderr = std::thread::Builder::new().spawn(move || {
+        let mut output = String::new();
+        _ = stderr.read_to_string(&mut output);
+        output
+    })?;
+
+    Ok(AnathemaThreadHandle {
+        worker: Worker::Process {
+            child,
+            stderr: Some(stderr),
+        },
+        buffer_receiver,
+        event_sender,
+        pending_frame: None,
+        launched: Instant::now(),
+        last_seen: Instant::now(),
+    })
+}
+
+thread_local!(static PANIC_SENDER: RefCell<Option<Sender<ThreadMessage>>> = Default::default());
+
+/// Reports panics on the current thread as a frame that shows the panic message, until
+/// [`remove_panic_hook`] is called.
+///
+/// The hook is global to the process, panics on every other thread are passed on to the hook that
+/// was set before, so in [`PreviewMode::Thread`] the editor's hook keeps working.
+pub(crate) fn install_panic_hook(panic_sender: Sender<ThreadMessage>) {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
         std::panic::set_hook(Box::new(move |info| {
+            // the thread-local is gone if the thread panics while it's being torn down
+            let panic_sender = PANIC_SENDER.try_with(|sender| sender.borrow().clone());
+            let Ok(Some(panic_sender)) = panic_sender else {
+                return previous(info);
+            };
             let payload = info.payload();
             let str = if let Some(&s) = payload.downcast_ref::<&'static str>() {
                 s
@@ -198,59 +843,172 @@ pub fn launch_threaded_anathema(
             } else {
                 "Box<dyn Any>"
             };
-            let panic_prefix = "Panic: ";
-            let mut width = 0;
-            let mut height = 3;
-            let mut last_width = panic_prefix.len();
-            for char in str.chars() {
-                if char == '\n' {
-                    width = width.max(last_width);
-                    last_width = 0;
-                    height += 1;
-                } else {
-                    last_width += 1;
-                }
-            }
-            width = width.max(last_width);
 
-            let mut buffer = RenderedBuffer::create(width, height);
+            let buffer = RenderedBuffer::message("Panic: ", str);
+            _ = panic_sender.send(ThreadMessage::Frame(FrameDiff::full(&buffer)));
+        }));
+    });
+    PANIC_SENDER.set(Some(panic_sender));
+}
 
-            for (x, c) in panic_prefix.chars().enumerate() {
-                buffer.set_at(x, 1, c, Style::reset());
-            }
+/// Stops reporting panics on the current thread, see [`install_panic_hook`].
+pub(crate) fn remove_panic_hook() {
+    PANIC_SENDER.take();
+}
 
-            for (y, line) in str.lines().enumerate() {
-                for (x, c) in line.chars().enumerate() {
-                    if c == '\n' {
-                        break;
-                    }
-                    if y == 0 {
-                        buffer.set_at(x + panic_prefix.len(), y + 1, c, Style::reset());
-                    } else {
-                        buffer.set_at(x, y + 1, c, Style::reset());
-                    }
-                }
+/// Compiles `document` and runs it until it stops or the editor goes away. With `state`, the
+/// document is registered as a component so the state can be its root state.
+pub(crate) fn run_anathema(
+    document: String,
+    components: Vec<(String, String)>,
+    state: Option<PreviewState>,
+    initial_size: Size,
+    buffer_sender: Sender<ThreadMessage>,
+    event_receiver: Receiver<ThreadEvent>,
+) {
+    let message_sender = buffer_sender.clone();
+    let (document, root) = match state {
+        Some(state) => (
+            Document::new(format!("@{ROOT_COMPONENT}")),
+            Some((document, state)),
+        ),
+        None => (Document::new(document), None),
+    };
+    let backend = ThreadBackend {
+        buffer: Buffer::new(initial_size),
+        buffer_sender,
+        event_receiver,
+        last_heartbeat: Instant::now(),
+        disconnected: false,
+        previous: RenderedBuffer::create(0, 0),
+        current: RenderedBuffer::create(0, 0),
+    };
+
+    let mut builder = Runtime::builder(document, backend);
+    for (name, template) in components {
+        if let Err(error) = builder.register_component(name.clone(), template.to_template(), (), ())
+        {
+            let mut error = CompileError::from_runtime(&error);
+            error.component = Some(name);
+            _ = message_sender.send(ThreadMessage::CompileError(error));
+            return;
+        }
+    }
+    if let Some((template, state)) = root {
+        let registered = builder.register_component(
+            ROOT_COMPONENT,
+            template.to_template(),
+            PreviewRoot,
+            state.root(),
+        );
+        if let Err(error) = registered {
+            let error = CompileError::from_runtime(&error);
+            _ = message_sender.send(ThreadMessage::CompileError(error));
+            return;
+        }
+    }
+
+    match builder.finish() {
+        Ok(mut runtime) => {
+            _ = message_sender.send(ThreadMessage::Ready);
+            runtime.run();
+        }
+        Err(error) => {
+            let error = CompileError::from_runtime(&error);
+            _ = message_sender.send(ThreadMessage::CompileError(error));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(rows: &[&str]) -> RenderedBuffer {
+        let width = rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut buffer = RenderedBuffer::create(width, rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate().filter(|(_, c)| *c != ' ') {
+                buffer.set_at(x, y, Cell::char(c, Style::reset()));
             }
+        }
+        buffer
+    }
 
-            _ = panic_sender.send(buffer);
-            //std::process::abort();
-        }));
-        let document = Document::new(document);
-        let backend = ThreadBackend {
-            buffer: Buffer::new(initial_size),
-            buffer_sender,
-            event_receiver,
-        };
+    fn same_frame(a: &RenderedBuffer, b: &RenderedBuffer) -> bool {
+        a.size() == b.size() && a.value == b.value
+    }
 
-        Runtime::builder(document, backend)
-            .finish()
-            .expect("we should never fail to compile the document")
-            .run();
-    })?;
+    #[test]
+    fn unchanged_frame_is_empty() {
+        let buffer = frame(&["abc", "def"]);
+        let diff = FrameDiff::between(&buffer, &buffer.clone());
+        assert!(diff.is_empty());
+        assert!(!diff.is_full());
+    }
 
-    Ok(AnathemaThreadHandle {
-        buffer_receiver,
-        event_sender,
-        thread_handle,
-    })
+    #[test]
+    fn changed_cells_are_grouped_into_runs() {
+        let previous = frame(&["abcdef", "ghijkl"]);
+        let current = frame(&["aXYdeZ", "ghijkl"]);
+        let diff = FrameDiff::between(&previous, &current);
+
+        let runs: Vec<_> = diff
+            .runs()
+            .iter()
+            .map(|run| (run.x, run.y, run.cells.len()))
+            .collect();
+        assert_eq!(runs, [(1, 0, 2), (5, 0, 1)]);
+        assert!(diff.runs()[0].cells[0] == Cell::char('X', Style::reset()));
+    }
+
+    #[test]
+    fn resized_frame_is_full() {
+        let previous = frame(&["ab", "cd"]);
+        let current = frame(&["abc", "def", "ghi"]);
+        let diff = FrameDiff::between(&previous, &current);
+        assert!(diff.is_full());
+        assert_eq!(diff.size(), Size::new(3, 3));
+        assert_eq!(diff.runs().len(), 3);
+    }
+
+    #[test]
+    fn applying_a_diff_gives_the_current_frame() {
+        let previous = frame(&["hello", "world", "     "]);
+        let current = frame(&["jello", "world", " !!  "]);
+        let mut buffer = previous.clone();
+        FrameDiff::between(&previous, &current).apply(&mut buffer);
+        assert!(same_frame(&buffer, &current));
+    }
+
+    #[test]
+    fn applying_a_full_diff_replaces_the_frame() {
+        let mut buffer = frame(&["old"]);
+        let current = frame(&["new", "frame"]);
+        FrameDiff::full(&current).apply(&mut buffer);
+        assert!(same_frame(&buffer, &current));
+    }
+
+    #[test]
+    fn runs_outside_of_the_frame_are_ignored() {
+        let mut buffer = frame(&["ab"]);
+        let runs = vec![
+            Run {
+                x: 1,
+                y: 0,
+                cells: vec![Cell::char('x', Style::reset()); 3],
+            },
+            Run {
+                x: 0,
+                y: 4,
+                cells: vec![Cell::char('y', Style::reset())],
+            },
+        ];
+        FrameDiff::new(2, 1, false, runs).apply(&mut buffer);
+        assert!(same_frame(&buffer, &frame(&["ax"])));
+    }
 }
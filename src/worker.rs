@@ -0,0 +1,67 @@
+use std::io::{BufReader, BufWriter};
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc::channel;
+
+use crate::protocol::{self, Launch};
+use crate::thread_backend::{install_panic_hook, remove_panic_hook, run_anathema, ThreadEvent};
+
+/// Runs the preview for an editor that started the playground with
+/// [`WORKER_FLAG`](crate::thread_backend::WORKER_FLAG).
+///
+/// The editor sends the template on stdin, followed by the events for the preview. The frames are
+/// written to stdout, anything written to stderr is shown to the user if the worker crashes.
+pub fn run() {
+    let mut stdin = BufReader::new(std::io::stdin());
+    let Ok(Some(launch)) = protocol::read_launch(&mut stdin) else {
+        return;
+    };
+
+    let (buffer_sender, buffer_receiver) = channel();
+    let (event_sender, event_receiver) = channel();
+
+    std::thread::spawn(move || loop {
+        match protocol::read_event(&mut stdin) {
+            Ok(Some(event)) => {
+                if event_sender.send(event).is_err() {
+                    break;
+                }
+            }
+            // the editor went away
+            _ => {
+                _ = event_sender.send(ThreadEvent::Quit);
+                break;
+            }
+        }
+    });
+    let writer = std::thread::spawn(move || {
+        let mut stdout = BufWriter::new(std::io::stdout());
+        for message in buffer_receiver {
+            if protocol::write_message(&mut stdout, &message).is_err() {
+                break;
+            }
+        }
+    });
+
+    install_panic_hook(buffer_sender.clone());
+    let Launch {
+        document,
+        components,
+        state,
+        size,
+    } = launch;
+    // a panic has already been sent to the editor by the hook
+    _ = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        run_anathema(
+            document,
+            components,
+            state,
+            size,
+            buffer_sender,
+            event_receiver,
+        )
+    }));
+
+    // the hook holds on to a sender, the writer only finishes once it's gone
+    remove_panic_hook();
+    _ = writer.join();
+}